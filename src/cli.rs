@@ -0,0 +1,123 @@
+use std::{fmt, path::PathBuf};
+
+pub const USAGE: &str = "\
+Usage: hexerhai <command> [options] <input>...
+
+Commands:
+    compile      compile scripts and emit the result in the chosen format
+    run          compile scripts and run them with the hexagon interpreter
+    check        compile scripts without emitting anything
+    dump-ast     print the parsed rhai ast
+    dump-flat    print the flattened ast
+
+Options:
+    -o, --output <path>      write output to <path> instead of stdout
+    -f, --format <format>    output format for `compile`: give-cmd (default), iotas
+    -c, --config <path>      hexagon project config with libraries, entities and great spell
+                             signatures, `config.toml` is used when it exists
+    -h, --help               print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Compile,
+    Run,
+    Check,
+    DumpAst,
+    DumpFlat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    GiveCmd,
+    Iotas,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+    Help,
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    UnknownFormat(String),
+    MissingValue(String),
+    NoInputs,
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{USAGE}"),
+            ArgsError::MissingCommand => write!(f, "no command given"),
+            ArgsError::UnknownCommand(command) => write!(f, "unknown command `{command}`"),
+            ArgsError::UnknownOption(option) => write!(f, "unknown option `{option}`"),
+            ArgsError::UnknownFormat(format) => write!(f, "unknown output format `{format}`"),
+            ArgsError::MissingValue(option) => write!(f, "option `{option}` expects a value"),
+            ArgsError::NoInputs => write!(f, "no input files given"),
+        }
+    }
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, ArgsError> {
+    let command = match args.next().as_deref() {
+        Some("compile") => Command::Compile,
+        Some("run") => Command::Run,
+        Some("check") => Command::Check,
+        Some("dump-ast") => Command::DumpAst,
+        Some("dump-flat") => Command::DumpFlat,
+        Some("-h" | "--help") => return Err(ArgsError::Help),
+        Some(command) => return Err(ArgsError::UnknownCommand(command.to_string())),
+        None => return Err(ArgsError::MissingCommand),
+    };
+
+    let mut inputs = vec![];
+    let mut output = None;
+    let mut format = OutputFormat::GiveCmd;
+    let mut config = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let path = args.next().ok_or(ArgsError::MissingValue(arg))?;
+                output = Some(PathBuf::from(path));
+            }
+            "-f" | "--format" => {
+                let value = args.next().ok_or(ArgsError::MissingValue(arg))?;
+                format = match value.as_str() {
+                    "give-cmd" => OutputFormat::GiveCmd,
+                    "iotas" => OutputFormat::Iotas,
+                    _ => return Err(ArgsError::UnknownFormat(value)),
+                };
+            }
+            "-c" | "--config" => {
+                let path = args.next().ok_or(ArgsError::MissingValue(arg))?;
+                config = Some(PathBuf::from(path));
+            }
+            "-h" | "--help" => return Err(ArgsError::Help),
+            option if option.starts_with('-') && option != "-" => {
+                return Err(ArgsError::UnknownOption(arg))
+            }
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(ArgsError::NoInputs);
+    }
+
+    Ok(Args {
+        command,
+        inputs,
+        output,
+        format,
+        config,
+    })
+}
@@ -0,0 +1,225 @@
+use std::{collections::HashMap, fmt, ops::Deref, rc::Rc};
+
+use hexagon::{
+    compiler::{compile_to_iotas, nbt::gen_give_cmd},
+    iota::Iota,
+    parse_config::Config,
+    parser::AstNode,
+    pattern_registry::{PatternRegistry, PatternRegistryExt},
+};
+use rhai::{Dynamic, Engine, ParseError, Position, AST};
+
+use crate::{
+    diagnostic::{render_diagnostics, Diagnostic},
+    entity::{entity_embeds, register_entities},
+    flatten_ast::{flatten_functions, flatten_script, FlatNode, FlattenContext},
+    flatten_custom::register_custom_syntax,
+    hex_module::{hex_actions, hex_module, HexAction},
+    infer_types::infer_types,
+    translate::translate_flattened_ast,
+    translate_dynamic::translate_dynamic_to_iota,
+    vector::register_vector,
+};
+
+#[derive(Debug)]
+pub enum CompileError {
+    /// The source is not valid rhai.
+    Parse(ParseError),
+    /// Parts of the script could not be lowered to hex casting.
+    Diagnostics(Vec<Diagnostic>),
+    /// hexagon rejected the translated program.
+    Hex(String),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Parse(err) => write!(f, "{err}"),
+            CompileError::Diagnostics(diagnostics) => {
+                let messages = diagnostics.iter().map(ToString::to_string);
+                write!(f, "{}", messages.collect::<Vec<_>>().join("\n"))
+            }
+            CompileError::Hex(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl CompileError {
+    /// Renders the error for the user, with a source snippet for each diagnostic.
+    pub fn render(&self, source: &str, source_path: &str) -> String {
+        match self {
+            CompileError::Diagnostics(diagnostics) => {
+                render_diagnostics(diagnostics, source, source_path)
+            }
+            err => format!("error: {source_path}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<ParseError> for CompileError {
+    fn from(err: ParseError) -> Self {
+        CompileError::Parse(err)
+    }
+}
+
+/// A parsed script together with its source text, which some lowerings read from.
+pub struct Script {
+    ast: AST,
+    source: String,
+}
+
+impl Script {
+    pub fn ast(&self) -> &AST {
+        &self.ast
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl Deref for Script {
+    type Target = AST;
+
+    fn deref(&self) -> &AST {
+        &self.ast
+    }
+}
+
+/// The rhai to hex casting pipeline: parse, flatten, translate and compile to iotas.
+pub struct Compiler {
+    engine: Engine,
+    config: Config,
+    pattern_registry: PatternRegistry,
+    hex_actions: HashMap<String, HexAction>,
+    entities: HashMap<String, Result<Vec<AstNode>, String>>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::with_config(Config {
+            libraries: HashMap::new(),
+            entities: HashMap::new(),
+            great_spell_sigs: PatternRegistry::gen_default_great_sigs(),
+        })
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let pattern_registry = PatternRegistry::construct(&config.great_spell_sigs);
+        let hex_actions = hex_actions(&pattern_registry);
+        let entities = entity_embeds(&config);
+
+        let mut engine = Engine::new();
+
+        engine.set_strict_variables(true);
+        engine.register_static_module("hex", hex_module(&hex_actions).into());
+        register_custom_syntax(&mut engine);
+        register_vector(&mut engine);
+        register_entities(&mut engine, config.entities.keys().cloned().collect());
+
+        Compiler {
+            engine,
+            config,
+            pattern_registry,
+            hex_actions,
+            entities,
+        }
+    }
+
+    /// The rhai engine used for parsing, with hex casting actions registered as the `hex` module
+    /// the `vector` and `entity` types, and the `patterns { ... }` and `pattern!(...)` syntax for writing
+    /// hex casting directly.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// The rhai engine, for setting callbacks such as `on_print` when scripts are run by rhai.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn pattern_registry(&self) -> &PatternRegistry {
+        &self.pattern_registry
+    }
+
+    pub fn parse(&self, source: &str) -> Result<Script, CompileError> {
+        Ok(Script {
+            ast: self.engine.compile(source)?,
+            source: source.to_string(),
+        })
+    }
+
+    /// Flattens a parsed script, collecting a diagnostic for every unsupported construct
+    /// and every call to a function that does not exist.
+    pub fn flatten(&self, script: &Script) -> Result<Vec<FlatNode>, CompileError> {
+        let ast = script.ast();
+        let actions = self
+            .pattern_registry
+            .iter()
+            .map(|pattern| pattern.internal_name.clone());
+        let signatures = self
+            .pattern_registry
+            .iter()
+            .map(|pattern| (pattern.signature.clone(), pattern.internal_name.clone()))
+            .collect();
+        let mut ctx = FlattenContext::with_source(script.source())
+            .with_actions(actions)
+            .with_hex_actions(self.hex_actions.clone())
+            .with_signatures(signatures)
+            .with_entities(self.entities.clone());
+        let mut flattened_ast = flatten_functions(ast.iter_fn_def().map(AsRef::as_ref), &mut ctx);
+        flattened_ast.append(&mut flatten_script(ast.statements(), &mut ctx));
+        let flattened_ast = infer_types(flattened_ast, &mut ctx);
+
+        if ctx.diagnostics.is_empty() {
+            Ok(flattened_ast)
+        } else {
+            Err(CompileError::Diagnostics(ctx.diagnostics))
+        }
+    }
+
+    /// Translates a parsed script into a hexagon program.
+    pub fn translate(&self, script: &Script) -> Result<Vec<AstNode>, CompileError> {
+        translate_flattened_ast(self.flatten(script)?).map_err(CompileError::Diagnostics)
+    }
+
+    pub fn compile_script(&self, script: &Script) -> Result<Vec<Rc<dyn Iota>>, CompileError> {
+        let translated_ast = self.translate(script)?;
+
+        compile_to_iotas(
+            &AstNode::Program(translated_ast),
+            None,
+            &self.pattern_registry,
+            &self.config.entities,
+        )
+        .map_err(|err| CompileError::Hex(format!("{:?}", err)))
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Vec<Rc<dyn Iota>>, CompileError> {
+        self.compile_script(&self.parse(source)?)
+    }
+
+    /// Compiles a script to a `/give` command for a focus holding the spell.
+    pub fn compile_to_give_cmd(&self, source: &str) -> Result<String, CompileError> {
+        Ok(gen_give_cmd(self.compile(source)?))
+    }
+
+    /// The iota a value computed by rhai is embedded as, e.g. to compare what a script
+    /// evaluates to in rhai with the result of the compiled spell.
+    pub fn value_to_iota(&self, value: Dynamic) -> Result<Rc<dyn Iota>, CompileError> {
+        translate_dynamic_to_iota(Box::new(value), Position::NONE)
+            .map_err(|diagnostic| CompileError::Diagnostics(vec![diagnostic]))
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+
+use rhai::Position;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub position: Position,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, position: Position) -> Self {
+        Diagnostic {
+            message: message.into(),
+            position,
+        }
+    }
+
+    /// Renders the diagnostic together with the offending source line, e.g.
+    ///
+    /// ```text
+    /// error: switch expressions are not supported yet
+    ///  --> test.rhai:3:1
+    ///   |
+    /// 3 | switch x {
+    ///   | ^
+    /// ```
+    pub fn render(&self, source: &str, source_path: &str) -> String {
+        let (Some(line), Some(column)) = (self.position.line(), self.position.position()) else {
+            return format!("error: {}\n --> {}", self.message, source_path);
+        };
+
+        let text = source.lines().nth(line - 1).unwrap_or_default();
+        let gutter = " ".repeat(line.to_string().len());
+
+        format!(
+            "error: {message}\n{gutter}--> {source_path}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {marker}^",
+            message = self.message,
+            marker = " ".repeat(column.saturating_sub(1)),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.position)
+    }
+}
+
+pub fn render_diagnostics(diagnostics: &[Diagnostic], source: &str, source_path: &str) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(source, source_path))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Finds the candidate closest to a misspelled name, if any is close enough to be a likely typo.
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+//levenshtein distance, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
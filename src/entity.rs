@@ -0,0 +1,59 @@
+use std::{collections::HashMap, fmt};
+
+use hexagon::{
+    parse_config::Config,
+    parser::{parse, AstNode},
+};
+use rhai::{Engine, EvalAltResult};
+
+/// The rhai side of the entities declared in the project config, so scripts using them
+/// also run in rhai.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub name: String,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Registers the `entity` type with `entity(name)`, which finds an entity declared in the config.
+pub fn register_entities(engine: &mut Engine, names: Vec<String>) {
+    engine
+        .register_type_with_name::<Entity>("entity")
+        .register_fn(
+            "entity",
+            move |name: &str| -> Result<Entity, Box<EvalAltResult>> {
+                if names.iter().any(|entity| entity == name) {
+                    Ok(Entity {
+                        name: name.to_string(),
+                    })
+                } else {
+                    Err(format!("unknown entity `{name}`").into())
+                }
+            },
+        )
+        .register_fn("to_string", |entity: &mut Entity| entity.to_string())
+        .register_fn("to_debug", |entity: &mut Entity| entity.to_string())
+        .register_fn("==", |a: Entity, b: Entity| a == b)
+        .register_fn("!=", |a: Entity, b: Entity| a != b);
+}
+
+/// Embeds each entity of the config, by name. hexagon reads the entity from its own syntax,
+/// so an entity it cannot embed keeps the error for when it is used.
+pub fn entity_embeds(config: &Config) -> HashMap<String, Result<Vec<AstNode>, String>> {
+    config
+        .entities
+        .keys()
+        .map(|name| {
+            let embed = match parse(&format!("<@{name}>"), "", config.entities.clone()) {
+                Ok(AstNode::Program(nodes)) => Ok(nodes),
+                Ok(node) => Ok(vec![node]),
+                Err(err) => Err(format!("{err:?}")),
+            };
+            (name.clone(), embed)
+        })
+        .collect()
+}
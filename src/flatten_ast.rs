@@ -1,82 +1,630 @@
-use std::ops::Not;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{Not, Range, RangeInclusive},
+};
 
-use rhai::{ASTFlags, Dynamic, Expr, FlowControl, FnCallExpr, Ident, Position, Stmt};
+use hexagon::parser::AstNode;
+use rhai::{
+    ASTFlags, BinaryExpr, Dynamic, Expr, FlowControl, FnCallExpr, Ident, Position, ScriptFnDef,
+    Stmt, INT,
+};
 use smallvec::SmallVec;
 
-pub fn flatten_statements(ast: &[Stmt]) -> Vec<FlatNode> {
+use crate::{
+    diagnostic::{did_you_mean, Diagnostic},
+    flatten_chain::{flatten_chain, flatten_chain_assignment, flatten_map},
+    flatten_custom::flatten_custom,
+    flatten_entity::flatten_entity,
+    flatten_std::{is_std_function, std_function, RHAI_STD_FUNCTIONS},
+    flatten_switch::flatten_switch,
+    flatten_vector::vector_function,
+    hex_module::HexAction,
+    infer_types::type_hint,
+    translate_ops::BUILTIN_FUNCTIONS,
+};
+
+#[derive(Debug, Default)]
+pub struct FlattenContext {
+    pub diagnostics: Vec<Diagnostic>,
+    functions: HashSet<(String, usize)>,
+    current_function: Option<(String, usize)>,
+    //calls between user-defined functions, checked for recursion once every body is flattened
+    calls: Vec<((String, usize), (String, usize), Position)>,
+    next_id: usize,
+    //ids of the enclosing loops, innermost last
+    loops: Vec<usize>,
+    //the script's source text, which switch cases are read from
+    pub(crate) source: String,
+    //names of the hex casting actions calls can resolve to, when they are checked
+    actions: Option<HashSet<String>>,
+    //actions callable through the `hex` namespace, when they are checked
+    hex_actions: Option<HashMap<String, HexAction>>,
+    //internal names of the patterns `pattern!` can resolve to, by signature
+    signatures: HashMap<String, String>,
+    //variables declared with a `//: type` comment, checked by infer_types
+    pub(crate) type_hints: Vec<(String, String, Position)>,
+    //the embeds of the entities declared in the config, by name
+    pub(crate) entities: HashMap<String, Result<Vec<AstNode>, String>>,
+}
+
+impl FlattenContext {
+    pub fn with_source(source: &str) -> Self {
+        FlattenContext {
+            source: source.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Reports calls to functions that are not user-defined, mapped from rhai
+    /// or one of the given hex casting actions.
+    pub fn with_actions(mut self, actions: impl IntoIterator<Item = String>) -> Self {
+        self.actions = Some(actions.into_iter().collect());
+        self
+    }
+
+    /// Reports calls through the `hex` namespace to actions that are not given,
+    /// or with the wrong number of arguments.
+    pub fn with_hex_actions(mut self, hex_actions: HashMap<String, HexAction>) -> Self {
+        self.hex_actions = Some(hex_actions);
+        self
+    }
+
+    /// Resolves `pattern!(signature, direction)` to the pattern with the given signature.
+    pub fn with_signatures(mut self, signatures: HashMap<String, String>) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    /// Lets `entity(name)` embed the given entities.
+    pub fn with_entities(
+        mut self,
+        entities: HashMap<String, Result<Vec<AstNode>, String>>,
+    ) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    pub fn error(&mut self, message: impl Into<String>, position: Position) {
+        self.diagnostics.push(Diagnostic::new(message, position))
+    }
+
+    //rhai functions cannot see the caller's variables, so their locals get their own names
+    pub(crate) fn variable(&self, name: &str) -> String {
+        match &self.current_function {
+            Some((function, arity)) => format!("{}/{}", function_variable(function, *arity), name),
+            None => name.to_string(),
+        }
+    }
+
+    fn is_function(&self, name: &str, arity: usize) -> bool {
+        self.functions.contains(&(name.to_string(), arity))
+    }
+
+    fn is_action(&self, name: &str) -> bool {
+        match &self.actions {
+            Some(actions) => actions.contains(name),
+            None => true,
+        }
+    }
+
+    fn unknown_function(&mut self, name: &str, arity: usize, position: Position) {
+        let mut arities = self
+            .functions
+            .iter()
+            .filter(|(function, _)| function == name)
+            .map(|(_, arity)| *arity)
+            .collect::<Vec<_>>();
+        if !arities.is_empty() {
+            arities.sort();
+            let arities = arities.iter().map(ToString::to_string).collect::<Vec<_>>();
+            let message = format!(
+                "`{name}` takes {} arguments but {arity} were given",
+                arities.join(" or ")
+            );
+            self.error(message, position);
+            return;
+        }
+
+        let functions = self.functions.iter().map(|(name, _)| name.as_str());
+        let actions = self.actions.iter().flatten().map(String::as_str);
+        //only names that can be written as a rhai function call are suggested
+        let candidates = functions
+            .chain(actions)
+            .chain(BUILTIN_FUNCTIONS.iter().copied())
+            .chain(RHAI_STD_FUNCTIONS.iter().copied())
+            .filter(|candidate| candidate.chars().all(|c| c.is_alphanumeric() || c == '_'));
+
+        let message = match did_you_mean(name, candidates) {
+            Some(suggestion) => format!(
+                "unknown function `{name}` with {arity} arguments, did you mean `{suggestion}`?"
+            ),
+            None => format!("unknown function `{name}` with {arity} arguments"),
+        };
+        self.error(message, position);
+    }
+
+    //the pattern called by `hex::name(...)`
+    fn hex_action(&mut self, name: &str, arity: usize, position: Position) -> Option<String> {
+        let Some(hex_actions) = &self.hex_actions else {
+            return Some(name.to_string());
+        };
+
+        let pattern = match hex_actions.get(name) {
+            Some(HexAction {
+                arity: expected, ..
+            }) if *expected != arity => Err(format!(
+                "`hex::{name}` takes {expected} arguments but {arity} were given"
+            )),
+            Some(action) => Ok(action.pattern.clone()),
+            None => Err(
+                match did_you_mean(name, hex_actions.keys().map(String::as_str)) {
+                    Some(suggestion) => format!(
+                        "unknown hex casting action `hex::{name}`, did you mean `hex::{suggestion}`?"
+                    ),
+                    None => format!("unknown hex casting action `hex::{name}`"),
+                },
+            ),
+        };
+
+        pattern
+            .map_err(|message| self.error(message, position))
+            .ok()
+    }
+
+    //how many iotas `hex::name(...)` pushes, which are popped when the call is a statement
+    fn hex_results(&self, name: &str) -> usize {
+        self.hex_actions
+            .iter()
+            .flat_map(|hex_actions| hex_actions.get(name))
+            .map(|action| action.results)
+            .sum()
+    }
+
+    pub(crate) fn pattern_with_signature(&self, signature: &str) -> Option<String> {
+        self.signatures.get(signature).cloned()
+    }
+
+    //used to give the hidden variables of nested constructs distinct names
+    pub(crate) fn unique_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+//the ravenmind variable holding the pattern list of a user-defined function
+pub fn function_variable(name: &str, arity: usize) -> String {
+    format!("{name}/{arity}")
+}
+
+/// Flattens user-defined functions into pattern lists stored in ravenmind variables.
+/// Must run before the script's statements are flattened so calls can be resolved.
+pub fn flatten_functions<'a>(
+    functions: impl IntoIterator<Item = &'a ScriptFnDef>,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let functions = functions.into_iter().collect::<Vec<_>>();
+
+    for function in &functions {
+        ctx.functions
+            .insert((function.name.to_string(), function.params.len()));
+    }
+
+    let mut flattened_ast: Vec<FlatNode> = vec![];
+
+    //every function variable is stored before any body is, so bodies can call functions declared after them
+    for function in &functions {
+        let position = function.body.position();
+        flattened_ast.push(FlatNode::Unit(position));
+        flattened_ast.push(FlatNode::Op(
+            Op::Store(function_variable(&function.name, function.params.len())),
+            position,
+        ));
+    }
+
+    for function in functions {
+        let name = function.name.to_string();
+        let arity = function.params.len();
+        let position = function.body.position();
+
+        ctx.current_function = Some((name.clone(), arity));
+
+        //called with Iris' Gambit, so the continuation sits on top of the arguments
+        let mut body = vec![FlatNode::Op(Op::Store(ctx.variable("return")), position)];
+        for param in function.params.iter().rev() {
+            body.push(FlatNode::Op(Op::Store(ctx.variable(param)), position));
+        }
+        body.append(&mut flatten_value_block(
+            function.body.statements(),
+            position,
+            ctx,
+        ));
+
+        ctx.current_function = None;
+
+        flattened_ast.push(FlatNode::Block(body, position));
+        flattened_ast.push(FlatNode::Op(
+            Op::Store(function_variable(&name, arity)),
+            position,
+        ));
+    }
+
+    report_recursion(ctx);
+
+    return flattened_ast;
+}
+
+//the locals of a function live in fixed variables, so a function cannot be called while it is running
+fn report_recursion(ctx: &mut FlattenContext) {
+    let calls = std::mem::take(&mut ctx.calls);
+
+    for (caller, callee, position) in &calls {
+        if calls_function(&calls, callee, caller) {
+            ctx.error("recursive functions are not supported yet", *position);
+        }
+    }
+}
+
+//whether `function` is `target` or calls it, directly or through other functions
+fn calls_function(
+    calls: &[((String, usize), (String, usize), Position)],
+    function: &(String, usize),
+    target: &(String, usize),
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut pending = vec![function];
+
+    while let Some(function) = pending.pop() {
+        if function == target {
+            return true;
+        }
+        if visited.insert(function) {
+            pending.extend(
+                calls
+                    .iter()
+                    .filter(|(caller, _, _)| caller == function)
+                    .map(|(_, callee, _)| callee),
+            );
+        }
+    }
+
+    false
+}
+
+/// Flattens the top level of a script. Like rhai's `eval`, a trailing expression
+/// is the result of the script and is left on the stack.
+pub fn flatten_script(statements: &[Stmt], ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    match statements.split_last() {
+        Some((Stmt::Expr(expr), rest)) => {
+            let mut flattened_ast = flatten_statements(rest, ctx);
+            flattened_ast.extend(flatten_expression(*expr.clone(), ctx).into_iter().rev());
+            flattened_ast
+        }
+        //a trailing call is not popped, but hex actions ending a script may push any number of iotas
+        Some((Stmt::FnCall(expr, position), rest)) => {
+            let mut flattened_ast = flatten_statements(rest, ctx);
+            flattened_ast.extend(
+                flatten_fn_call_expression(*expr.clone(), *position, false, ctx)
+                    .into_iter()
+                    .rev(),
+            );
+            flattened_ast
+        }
+        _ => flatten_statements(statements, ctx),
+    }
+}
+
+//like flatten_statements, but the value of the last statement is left on the stack
+pub(crate) fn flatten_value_block(
+    statements: &[Stmt],
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let Some((last, rest)) = statements.split_last() else {
+        return vec![FlatNode::Unit(position)];
+    };
+
+    let mut flattened_ast = flatten_statements(rest, ctx);
+
+    match last {
+        Stmt::Expr(expr) => {
+            flattened_ast.extend(flatten_expression(*expr.clone(), ctx).into_iter().rev())
+        }
+        Stmt::FnCall(expr, position) => flattened_ast.extend(
+            flatten_fn_call_expression(*expr.clone(), *position, true, ctx)
+                .into_iter()
+                .rev(),
+        ),
+        Stmt::Block(block) => flattened_ast.append(&mut flatten_value_block(
+            block.statements(),
+            block.position(),
+            ctx,
+        )),
+        Stmt::If(data, position) => {
+            flattened_ast.extend(flatten_if_value(data, *position, ctx).into_iter().rev())
+        }
+        Stmt::Switch(data, position) => {
+            flattened_ast.extend(flatten_switch(data, *position, true, ctx).into_iter().rev())
+        }
+        statement => {
+            flattened_ast.append(&mut flatten_statements(
+                std::slice::from_ref(statement),
+                ctx,
+            ));
+            flattened_ast.push(FlatNode::Unit(statement.position()));
+        }
+    }
+
+    return flattened_ast;
+}
+
+pub fn flatten_statements(ast: &[Stmt], ctx: &mut FlattenContext) -> Vec<FlatNode> {
     let mut flattened_ast: Vec<FlatNode> = vec![];
 
-    for statement in ast {
+    for (i, statement) in ast.iter().enumerate() {
         let mut flattened_ast_statment: Vec<FlatNode> = vec![];
 
         match statement {
-            Stmt::Expr(_) => todo!(),
-            Stmt::Var(data, _, _) => {
-                flattened_ast_statment.append(&mut flatten_var((data.0.clone(), data.1.clone())))
+            //custom syntax leaves the stack as its patterns do
+            Stmt::Expr(expr) if matches!(**expr, Expr::Custom(..)) => {
+                flattened_ast_statment.append(&mut flatten_expression(*expr.clone(), ctx));
+            }
+            Stmt::Expr(expr) => {
+                flattened_ast_statment.push(FlatNode::Op(Op::Pop, expr.position()));
+                flattened_ast_statment.append(&mut flatten_expression(*expr.clone(), ctx));
             }
+            Stmt::Var(data, _, _) => flattened_ast_statment
+                .append(&mut flatten_var((data.0.clone(), data.1.clone()), ctx)),
 
             Stmt::Assignment(data) => {
-                let (variable, position) = if let Expr::Variable(ref var_data, _, pos) = data.1.lhs
-                {
-                    (var_data.3.clone().to_string(), pos)
-                } else {
-                    panic!()
-                };
+                let op = data
+                    .0
+                    .clone()
+                    .get_op_assignment_info()
+                    .map(|x| x.5.to_string());
 
-                flattened_ast_statment.push(FlatNode::Op(Op::Store(variable.clone()), position));
-                if let Some(op) = data.0.clone().get_op_assignment_info().map(|x| x.5) {
-                    flattened_ast_statment.push(FlatNode::Op(Op::FnCall(op.to_string()), position));
-                    flattened_ast_statment.append(&mut flatten_expression(data.1.rhs.clone()));
-                    flattened_ast_statment.push(FlatNode::Op(Op::Push(variable), position));
-                } else {
-                    flattened_ast_statment.append(&mut flatten_expression(data.1.rhs.clone()));
+                match data.1.lhs {
+                    Expr::Variable(ref var_data, _, position) => {
+                        let variable = ctx.variable(&var_data.3);
+
+                        flattened_ast_statment
+                            .push(FlatNode::Op(Op::Store(variable.clone()), position));
+                        if let Some(op) = op {
+                            flattened_ast_statment.push(FlatNode::Op(Op::FnCall(op), position));
+                            flattened_ast_statment
+                                .append(&mut flatten_expression(data.1.rhs.clone(), ctx));
+                            flattened_ast_statment.push(FlatNode::Op(Op::Push(variable), position));
+                        } else {
+                            flattened_ast_statment
+                                .append(&mut flatten_expression(data.1.rhs.clone(), ctx));
+                        }
+                    }
+                    ref lhs @ (Expr::Dot(..) | Expr::Index(..)) => flattened_ast_statment.extend(
+                        flatten_chain_assignment(lhs.clone(), op, data.1.rhs.clone(), ctx)
+                            .into_iter()
+                            .rev(),
+                    ),
+                    ref lhs => ctx.error(
+                        "assignment to this expression is not supported yet",
+                        lhs.position(),
+                    ),
                 }
             }
-            Stmt::FnCall(expr, position) => flattened_ast_statment
-                .append(&mut flatten_fn_call_expression(*expr.clone(), *position)),
-            Stmt::If(data, position) => {
-                flattened_ast_statment.append(&mut flatten_if(data, *position))
+            Stmt::FnCall(expr, position) => {
+                //user-defined functions, mapped functions and hex casting actions push
+                //their results, which are unused here
+                let (name, arity) = (expr.name.as_str(), expr.args.len());
+                if expr.namespace.is_empty()
+                    && (ctx.is_function(name, arity)
+                        || std_function(name, arity, *position).is_some()
+                        || vector_function(name, arity, *position).is_some())
+                {
+                    flattened_ast_statment.push(FlatNode::Op(Op::Pop, *position));
+                } else if calls_hex_namespace(expr) {
+                    for _ in 0..ctx.hex_results(&expr.name) {
+                        flattened_ast_statment.push(FlatNode::Op(Op::Pop, *position));
+                    }
+                }
+                flattened_ast_statment.append(&mut flatten_fn_call_expression(
+                    *expr.clone(),
+                    *position,
+                    false,
+                    ctx,
+                ))
             }
-            Stmt::While(data, position) => {
-                flattened_ast_statment.append(&mut flatten_while(true, false, data, *position))
+            Stmt::If(data, position) => {
+                flattened_ast_statment.append(&mut flatten_if(data, *position, ctx))
             }
+            Stmt::While(data, position) => flattened_ast_statment
+                .append(&mut flatten_while(false, false, data, *position, ctx)),
+            //the body of a do loop runs before its condition is first checked
             Stmt::Do(data, flag, position) => {
                 if let ASTFlags::NEGATED = *flag {
-                    flattened_ast_statment.append(&mut flatten_while(true, true, data, *position))
+                    flattened_ast_statment
+                        .append(&mut flatten_while(true, true, data, *position, ctx))
                 } else {
-                    flattened_ast_statment.append(&mut flatten_while(true, false, data, *position))
+                    flattened_ast_statment
+                        .append(&mut flatten_while(true, false, data, *position, ctx))
                 }
             }
 
-            Stmt::Noop(_) => todo!(),
-            Stmt::Switch(_, _) => todo!(),
-            Stmt::For(_, _) => todo!(),
-            Stmt::Block(_) => todo!(),
-            Stmt::TryCatch(_, _) => todo!(),
-            Stmt::BreakLoop(_, _, _) => todo!(),
-            Stmt::Return(_, _, _) => todo!(),
-            Stmt::Import(_, _) => todo!(),
-            Stmt::Export(_, _) => todo!(),
-            Stmt::Share(_) => todo!(),
-            _ => todo!(),
+            Stmt::Noop(_) => (),
+            Stmt::Switch(data, position) => {
+                flattened_ast_statment.append(&mut flatten_switch(data, *position, false, ctx))
+            }
+            Stmt::For(data, position) => {
+                flattened_ast_statment.append(&mut flatten_for(data, *position, ctx))
+            }
+            Stmt::Block(block) => flattened_ast_statment.extend(
+                flatten_statements(block.statements(), ctx)
+                    .into_iter()
+                    .rev(),
+            ),
+            Stmt::TryCatch(_, position) => {
+                ctx.error("try/catch blocks are not supported", *position)
+            }
+            Stmt::BreakLoop(value, flags, position) => {
+                let Some(&id) = ctx.loops.last() else {
+                    ctx.error("break and continue must be inside a loop", *position);
+                    continue;
+                };
+
+                let flag = if flags.contains(ASTFlags::BREAK) {
+                    loop_flag(id, "break")
+                } else {
+                    loop_flag(id, "continue")
+                };
+
+                //loops are statements, so a break value would have nowhere to go
+                if value.is_some() {
+                    ctx.error("break with a value is not supported", *position);
+                }
+
+                flattened_ast_statment.push(FlatNode::Op(Op::Store(flag), *position));
+                flattened_ast_statment.push(FlatNode::BooleanLiteral(true, *position));
+            }
+            Stmt::Return(_, flags, position) if flags.contains(ASTFlags::BREAK) => {
+                ctx.error("throw is not supported", *position)
+            }
+            Stmt::Return(value, _, position) => {
+                if ctx.current_function.is_some() {
+                    flattened_ast_statment
+                        .push(FlatNode::Op(Op::Return(ctx.variable("return")), *position));
+                    match value {
+                        Some(value) => flattened_ast_statment
+                            .append(&mut flatten_expression(*value.clone(), ctx)),
+                        None => flattened_ast_statment.push(FlatNode::Unit(*position)),
+                    }
+                } else {
+                    ctx.error("return outside of a function is not supported", *position)
+                }
+            }
+            Stmt::Import(_, position) => ctx.error("modules are not supported", *position),
+            Stmt::Export(_, position) => ctx.error("modules are not supported", *position),
+            Stmt::Share(_) => ctx.error("shared variables are not supported", statement.position()),
+            _ => ctx.error("this statement is not supported yet", statement.position()),
         }
 
         flattened_ast_statment.reverse();
-        flattened_ast.append(&mut flattened_ast_statment)
+        let exits = ctx
+            .loops
+            .last()
+            .is_some_and(|&id| exits_loop(id, &flattened_ast_statment));
+        flattened_ast.append(&mut flattened_ast_statment);
+
+        //the rest of a loop body is skipped once `break` or `continue` has run
+        if let Some(&id) = ctx.loops.last() {
+            let rest = &ast[i + 1..];
+            if !rest.is_empty() && exits {
+                let position = rest[0].position();
+                flattened_ast.push(FlatNode::IfBlock {
+                    condition: vec![
+                        FlatNode::Op(Op::Push(loop_flag(id, "break")), position),
+                        FlatNode::Op(Op::Push(loop_flag(id, "continue")), position),
+                        FlatNode::Op(Op::FnCall("or".to_string()), position),
+                    ],
+                    succeed: vec![],
+                    fail: Some(flatten_statements(rest, ctx)),
+                    position,
+                });
+                flattened_ast.push(FlatNode::Op(Op::FnCall("eval".to_string()), position));
+                break;
+            }
+        }
     }
 
     return flattened_ast;
 }
 
-fn flatten_if(data: &Box<FlowControl>, position: Position) -> Vec<FlatNode> {
-    let condition = flatten_expression(data.expr.clone())
+fn loop_flag(id: usize, name: &str) -> String {
+    format!("loop/{id}/{name}")
+}
+
+//whether the flattened ast sets the `break` or `continue` flag of the loop,
+//wherever the `break` or `continue` is nested
+fn exits_loop(id: usize, flattened_ast: &[FlatNode]) -> bool {
+    let flags = [loop_flag(id, "break"), loop_flag(id, "continue")];
+
+    flattened_ast.iter().any(|node| match node {
+        FlatNode::Op(Op::Store(variable), _) => flags.contains(variable),
+        FlatNode::IfBlock {
+            condition,
+            succeed,
+            fail,
+            ..
+        } => {
+            exits_loop(id, condition)
+                || exits_loop(id, succeed)
+                || fail.as_deref().is_some_and(|fail| exits_loop(id, fail))
+        }
+        FlatNode::WhileBlock {
+            condition, block, ..
+        } => exits_loop(id, condition) || exits_loop(id, block),
+        FlatNode::Block(nodes, _) => exits_loop(id, nodes),
+        _ => false,
+    })
+}
+
+//loops that can be left early track `break` and `continue` in hidden flag variables
+fn flatten_loop(
+    id: usize,
+    do_while: bool,
+    condition: Vec<FlatNode>,
+    block: Vec<FlatNode>,
+    exits: bool,
+    position: Position,
+) -> Vec<FlatNode> {
+    if !exits {
+        return vec![FlatNode::WhileBlock {
+            do_while,
+            condition,
+            block,
+            position,
+        }];
+    }
+
+    let (break_flag, continue_flag) = (loop_flag(id, "break"), loop_flag(id, "continue"));
+
+    let condition = vec![
+        FlatNode::IfBlock {
+            condition: vec![FlatNode::Op(Op::Push(break_flag.clone()), position)],
+            succeed: vec![FlatNode::BooleanLiteral(false, position)],
+            fail: Some(condition),
+            position,
+        },
+        FlatNode::Op(Op::FnCall("eval".to_string()), position),
+    ];
+
+    let mut reset_continue = vec![
+        FlatNode::BooleanLiteral(false, position),
+        FlatNode::Op(Op::Store(continue_flag), position),
+    ];
+    reset_continue.extend(block);
+
+    return vec![
+        FlatNode::WhileBlock {
+            do_while,
+            condition,
+            block: reset_continue,
+            position,
+        },
+        FlatNode::Op(Op::Store(break_flag), position),
+        FlatNode::BooleanLiteral(false, position),
+    ];
+}
+
+fn flatten_if(
+    data: &Box<FlowControl>,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let condition = flatten_expression(data.expr.clone(), ctx)
         .into_iter()
         .rev()
         .collect::<Vec<_>>();
-    let succeed = flatten_statements(data.body.statements());
+    let succeed = flatten_statements(data.body.statements(), ctx);
 
     let fail = if data.branch.is_empty().not() {
-        Some(flatten_statements(data.branch.statements()))
+        Some(flatten_statements(data.branch.statements(), ctx))
     } else {
         None
     };
@@ -92,59 +640,237 @@ fn flatten_if(data: &Box<FlowControl>, position: Position) -> Vec<FlatNode> {
     ];
 }
 
+//an if used as an expression, where each branch leaves its value on the stack
+fn flatten_if_value(
+    data: &FlowControl,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let condition = flatten_expression(data.expr.clone(), ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+    let succeed = flatten_value_block(data.body.statements(), position, ctx);
+    let fail = flatten_value_block(data.branch.statements(), position, ctx);
+
+    return vec![
+        FlatNode::Op(Op::FnCall("eval".to_string()), position),
+        FlatNode::IfBlock {
+            condition,
+            succeed,
+            fail: Some(fail),
+            position,
+        },
+    ];
+}
+
 fn flatten_while(
     do_while: bool,
     negate_condition: bool,
     data: &Box<FlowControl>,
     position: Position,
+    ctx: &mut FlattenContext,
 ) -> Vec<FlatNode> {
-    let mut condition = flatten_expression(data.expr.clone())
-        .into_iter()
-        .rev()
-        .collect::<Vec<_>>();
+    let mut condition = match data.expr {
+        //`loop` is a while loop without a condition, which rhai gives no position
+        Expr::Unit(_) => vec![FlatNode::BooleanLiteral(true, position)],
+        ref expr => flatten_expression(expr.clone(), ctx)
+            .into_iter()
+            .rev()
+            .collect::<Vec<_>>(),
+    };
 
     if negate_condition {
         condition.push(FlatNode::Op(Op::FnCall("not".to_string()), position))
     }
 
-    let block = flatten_statements(data.body.statements());
+    let id = ctx.unique_id();
+    ctx.loops.push(id);
+    let block = flatten_statements(data.body.statements(), ctx);
+    ctx.loops.pop();
+
+    let exits = exits_loop(id, &block);
+    return flatten_loop(id, do_while, condition, block, exits, position);
+}
+
+enum ForIterable {
+    List(Vec<FlatNode>),
+    Range {
+        start: Vec<FlatNode>,
+        end: Vec<FlatNode>,
+        inclusive: bool,
+    },
+}
+
+fn for_iterable(expr: Expr, ctx: &mut FlattenContext) -> ForIterable {
+    match expr {
+        Expr::FnCall(call, _)
+            if call.args.len() == 2 && (call.name == ".." || call.name == "..=") =>
+        {
+            let inclusive = call.name == "..=";
+            let mut args = call.args.into_iter();
+            let start = flatten_expression(args.next().unwrap(), ctx);
+            let end = flatten_expression(args.next().unwrap(), ctx);
+            ForIterable::Range {
+                start,
+                end,
+                inclusive,
+            }
+        }
+        //constant ranges are folded by rhai's optimizer
+        Expr::DynamicConstant(val, position) if val.is::<Range<INT>>() => {
+            let range = (*val).cast::<Range<INT>>();
+            ForIterable::Range {
+                start: vec![FlatNode::IntegerLiteral(range.start, position)],
+                end: vec![FlatNode::IntegerLiteral(range.end, position)],
+                inclusive: false,
+            }
+        }
+        Expr::DynamicConstant(val, position) if val.is::<RangeInclusive<INT>>() => {
+            let range = (*val).cast::<RangeInclusive<INT>>();
+            ForIterable::Range {
+                start: vec![FlatNode::IntegerLiteral(*range.start(), position)],
+                end: vec![FlatNode::IntegerLiteral(*range.end(), position)],
+                inclusive: true,
+            }
+        }
+        expr => ForIterable::List(flatten_expression(expr, ctx)),
+    }
+}
+
+//lowered to a while loop over a hidden iteration counter, an integer like rhai's:
+//  let len = ...; let count = 0;
+//  while count < len { let x = <element count>; count += 1; ... }
+fn flatten_for(
+    data: &(Ident, Ident, FlowControl),
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let (variable, counter, body) = data;
+
+    let id = ctx.unique_id();
+    let hidden = |name: &str| format!("for/{id}/{name}");
+    let (len, count) = (hidden("len"), hidden("count"));
+
+    //built in reverse like every statement, then reversed at the end
+    let mut setup: Vec<FlatNode> = vec![];
+    let mut element: Vec<FlatNode> = vec![];
+
+    match for_iterable(body.expr.clone(), ctx) {
+        ForIterable::List(mut list) => {
+            setup.push(FlatNode::Op(Op::Store(len.clone()), position));
+            setup.push(FlatNode::Op(Op::FnCall("list_size".to_string()), position));
+            setup.push(FlatNode::Op(Op::Push(hidden("list")), position));
+            setup.push(FlatNode::Op(Op::Store(hidden("list")), position));
+            setup.append(&mut list);
+
+            element.push(FlatNode::Op(Op::FnCall("index".to_string()), position));
+            element.push(FlatNode::Op(Op::Push(count.clone()), position));
+            element.push(FlatNode::Op(Op::Push(hidden("list")), position));
+        }
+        ForIterable::Range {
+            mut start,
+            mut end,
+            inclusive,
+        } => {
+            setup.push(FlatNode::Op(Op::Store(len.clone()), position));
+            if inclusive {
+                setup.push(FlatNode::Op(Op::FnCall("+".to_string()), position));
+                setup.push(FlatNode::IntegerLiteral(1, position));
+            }
+            setup.push(FlatNode::Op(Op::FnCall("-".to_string()), position));
+            setup.push(FlatNode::Op(Op::Push(hidden("start")), position));
+            setup.append(&mut end);
+            setup.push(FlatNode::Op(Op::Store(hidden("start")), position));
+            setup.append(&mut start);
+
+            element.push(FlatNode::Op(Op::FnCall("+".to_string()), position));
+            element.push(FlatNode::Op(Op::Push(count.clone()), position));
+            element.push(FlatNode::Op(Op::Push(hidden("start")), position));
+        }
+    }
+
+    let mut init = vec![
+        FlatNode::Op(Op::Store(count.clone()), position),
+        FlatNode::IntegerLiteral(0, position),
+    ];
+    init.append(&mut setup);
+    init.reverse();
+
+    let condition = vec![
+        FlatNode::Op(Op::Push(count.clone()), position),
+        FlatNode::Op(Op::Push(len), position),
+        FlatNode::Op(Op::FnCall("<".to_string()), position),
+    ];
+
+    let mut block = vec![
+        FlatNode::Op(Op::Store(count.clone()), position),
+        FlatNode::Op(Op::FnCall("+".to_string()), position),
+        FlatNode::IntegerLiteral(1, position),
+        FlatNode::Op(Op::Push(count.clone()), position),
+    ];
+    if !counter.name.is_empty() {
+        block.push(FlatNode::Op(
+            Op::Store(ctx.variable(&counter.name)),
+            counter.pos,
+        ));
+        block.push(FlatNode::Op(Op::Push(count.clone()), counter.pos));
+    }
+    block.push(FlatNode::Op(
+        Op::Store(ctx.variable(&variable.name)),
+        variable.pos,
+    ));
+    block.append(&mut element);
+    block.reverse();
+
+    //incremented before the body runs so `continue` cannot skip it
+    ctx.loops.push(id);
+    block.append(&mut flatten_statements(body.body.statements(), ctx));
+    ctx.loops.pop();
+
+    let exits = exits_loop(id, &block);
+    let mut lowered_loop = flatten_loop(id, false, condition, block, exits, position);
+    lowered_loop.reverse();
+    init.append(&mut lowered_loop);
 
-    return vec![FlatNode::WhileBlock {
-        do_while,
-        condition: condition,
-        block: block,
-        position,
-    }];
+    //flatten_statements reverses each statement
+    init.reverse();
+    return init;
 }
 
-fn flatten_var(data: (Ident, Expr)) -> Vec<FlatNode> {
+fn flatten_var(data: (Ident, Expr), ctx: &mut FlattenContext) -> Vec<FlatNode> {
     let mut flattened_ast: Vec<FlatNode> = vec![];
 
     let identifier = data.0;
     let expression = data.1;
 
+    if let Some(hint) = type_hint(&ctx.source, identifier.pos) {
+        let variable = ctx.variable(&identifier.name);
+        ctx.type_hints.push((variable, hint, identifier.pos));
+    }
+
     flattened_ast.push(FlatNode::Op(
-        Op::Store(identifier.name.to_string()),
+        Op::Store(ctx.variable(&identifier.name)),
         identifier.pos,
     ));
-    flattened_ast.append(&mut flatten_expression(expression));
+    flattened_ast.append(&mut flatten_expression(expression, ctx));
 
     return flattened_ast;
 }
 
-fn flatten_expression(expression: Expr) -> Vec<FlatNode> {
+pub(crate) fn flatten_expression(expression: Expr, ctx: &mut FlattenContext) -> Vec<FlatNode> {
     let mut flattened_ast: Vec<FlatNode> = vec![];
 
     match expression {
         Expr::FnCall(expr, position) => {
-            flattened_ast.append(&mut flatten_fn_call_expression(*expr, position))
+            flattened_ast.append(&mut flatten_fn_call_expression(*expr, position, true, ctx))
         }
         Expr::Variable(data, _, position) => {
-            flattened_ast.push(FlatNode::Op(Op::Push(data.3.to_string()), position))
+            flattened_ast.push(FlatNode::Op(Op::Push(ctx.variable(&data.3)), position))
         }
 
         Expr::IntegerConstant(val, position) => {
-            flattened_ast.push(FlatNode::NumberLiteral(val as f64, position))
+            flattened_ast.push(FlatNode::IntegerLiteral(val, position))
         }
         Expr::FloatConstant(val, position) => {
             flattened_ast.push(FlatNode::NumberLiteral(*val, position))
@@ -170,36 +896,121 @@ fn flatten_expression(expression: Expr) -> Vec<FlatNode> {
             flattened_ast.push(FlatNode::NumberLiteral(val.len() as f64, position));
             val.into_iter()
                 .rev()
-                .for_each(|v| flattened_ast.append(&mut flatten_expression(v)));
+                .for_each(|v| flattened_ast.append(&mut flatten_expression(v, ctx)));
         }
         Expr::InterpolatedString(val, position) => {
-            flattened_ast.append(&mut flatten_interpolated_string(val, position));
-        }
-
-        Expr::Map(_, _) => todo!(),
-
-        Expr::ThisPtr(_) => todo!(),
-        Expr::Property(_, _) => todo!(),
-        Expr::MethodCall(_, _) => todo!(),
-        Expr::Stmt(_) => todo!(),
-        Expr::Dot(_, _, _) => todo!(),
-        Expr::Index(_, _, _) => todo!(),
-        Expr::And(_, _) => todo!(),
-        Expr::Or(_, _) => todo!(),
-        Expr::Coalesce(_, _) => todo!(),
-        Expr::Custom(_, _) => todo!(),
-        _ => todo!(),
+            flattened_ast.append(&mut flatten_interpolated_string(val, position, ctx));
+        }
+
+        Expr::Map(data, position) => {
+            flattened_ast.extend(flatten_map(&data.0, position, ctx).into_iter().rev())
+        }
+
+        Expr::ThisPtr(position) => ctx.error("`this` is not supported", position),
+        Expr::Property(_, position) => ctx.error("properties are not supported yet", position),
+        Expr::MethodCall(_, position) => ctx.error("method calls are not supported yet", position),
+        Expr::Stmt(block) => flattened_ast.extend(
+            flatten_value_block(block.statements(), block.position(), ctx)
+                .into_iter()
+                .rev(),
+        ),
+        expression @ (Expr::Dot(..) | Expr::Index(..)) => {
+            flattened_ast.extend(flatten_chain(expression, ctx).into_iter().rev())
+        }
+        Expr::And(data, position) => {
+            flattened_ast.append(&mut flatten_short_circuit(*data, false, position, ctx))
+        }
+        Expr::Or(data, position) => {
+            flattened_ast.append(&mut flatten_short_circuit(*data, true, position, ctx))
+        }
+        Expr::Coalesce(data, position) => {
+            flattened_ast.append(&mut flatten_coalesce(*data, position, ctx))
+        }
+        Expr::Custom(custom, position) => {
+            flattened_ast.append(&mut flatten_custom(&custom, position, ctx))
+        }
+        expression => ctx.error(
+            "this expression is not supported yet",
+            expression.position(),
+        ),
     }
 
     return flattened_ast;
 }
 
-fn flatten_interpolated_string(val: Box<SmallVec<[Expr; 5]>>, position: Position) -> Vec<FlatNode> {
+//`a && b` is `if a { b } else { false }` and `a || b` is `if a { true } else { b }`,
+//so the rhs is only evaluated when it decides the result
+fn flatten_short_circuit(
+    data: BinaryExpr,
+    is_or: bool,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let condition = flatten_expression(data.lhs, ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+    let rhs = flatten_expression(data.rhs, ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+    let constant = vec![FlatNode::BooleanLiteral(is_or, position)];
+
+    let (succeed, fail) = if is_or {
+        (constant, rhs)
+    } else {
+        (rhs, constant)
+    };
+
+    return vec![
+        FlatNode::Op(Op::FnCall("eval".to_string()), position),
+        FlatNode::IfBlock {
+            condition,
+            succeed,
+            fail: Some(fail),
+            position,
+        },
+    ];
+}
+
+//`a ?? b` keeps `a` unless it is null, in which case it is replaced by `b`,
+//which is only evaluated then
+fn flatten_coalesce(
+    data: BinaryExpr,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let mut replace = vec![FlatNode::Op(Op::Pop, position)];
+    replace.extend(flatten_expression(data.rhs, ctx).into_iter().rev());
+
+    let mut flattened_ast = vec![
+        FlatNode::Op(Op::FnCall("eval".to_string()), position),
+        FlatNode::IfBlock {
+            condition: vec![
+                FlatNode::Op(Op::FnCall("duplicate".to_string()), position),
+                FlatNode::Op(Op::FnCall("const/null".to_string()), position),
+                FlatNode::Op(Op::FnCall("==".to_string()), position),
+            ],
+            succeed: replace,
+            fail: Some(vec![]),
+            position,
+        },
+    ];
+    flattened_ast.append(&mut flatten_expression(data.lhs, ctx));
+
+    return flattened_ast;
+}
+
+fn flatten_interpolated_string(
+    val: Box<SmallVec<[Expr; 5]>>,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
     val.into_iter()
         .enumerate()
         .flat_map(|(i, v)| {
             let mut intrs = vec![];
-            let expr = &mut flatten_expression(v);
+            let expr = &mut flatten_expression(v, ctx);
 
             let mut is_string = false;
             if expr.len() == 1 {
@@ -224,19 +1035,97 @@ fn flatten_interpolated_string(val: Box<SmallVec<[Expr; 5]>>, position: Position
         .collect()
 }
 
-fn flatten_fn_call_expression(expression: FnCallExpr, position: Position) -> Vec<FlatNode> {
+//`keep_value` is set when the result of the call is used, rather than popped by a statement
+fn flatten_fn_call_expression(
+    expression: FnCallExpr,
+    position: Position,
+    keep_value: bool,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
     let mut flattened_ast: Vec<FlatNode> = vec![];
 
-    flattened_ast.push(FlatNode::Op(
-        Op::FnCall(expression.name.to_string()),
-        position,
-    ));
+    let name = expression.name.to_string();
+    let arity = expression.args.len();
+
+    if calls_hex_namespace(&expression) {
+        if let Some(pattern) = ctx.hex_action(&name, arity, position) {
+            let results = ctx.hex_results(&name);
+            if keep_value && results != 1 {
+                ctx.error(
+                    format!(
+                        "`hex::{name}` pushes {results} iotas, so it can only be called as a statement"
+                    ),
+                    position,
+                );
+            }
+            flattened_ast.push(FlatNode::Op(Op::Action(pattern), position));
+        }
+    } else if !expression.namespace.is_empty() {
+        ctx.error(
+            format!(
+                "the module `{}` does not exist, only `hex` is supported",
+                expression.namespace
+            ),
+            position,
+        );
+    } else if ctx.is_function(&name, arity) {
+        if let Some(caller) = ctx.current_function.clone() {
+            ctx.calls.push((caller, (name.clone(), arity), position));
+        }
+
+        flattened_ast.push(FlatNode::Op(
+            Op::Call(function_variable(&name, arity)),
+            position,
+        ));
+    } else if name == "entity" && arity == 1 {
+        return flatten_entity(&expression.args, position, ctx);
+    } else if (name == "-" || name == "+") && arity == 1 {
+        return flatten_unary(&name, expression.args[0].clone(), position, ctx);
+    } else if let Some(std_function) = std_function(&name, arity, position) {
+        flattened_ast.extend(std_function.into_iter().rev());
+    } else if let Some(vector_function) = vector_function(&name, arity, position) {
+        flattened_ast.extend(vector_function.into_iter().rev());
+    } else if is_std_function(&name) {
+        ctx.error(
+            format!(
+                "`{name}` with {arity} arguments from rhai's standard library is not supported"
+            ),
+            position,
+        );
+    } else if BUILTIN_FUNCTIONS.contains(&name.as_str()) || ctx.is_action(&name) {
+        flattened_ast.push(FlatNode::Op(Op::FnCall(name), position));
+    } else {
+        ctx.unknown_function(&name, arity, position);
+    }
 
     expression
         .args
         .into_iter()
         .rev()
-        .for_each(|arg| flattened_ast.append(&mut flatten_expression(arg.clone())));
+        .for_each(|arg| flattened_ast.append(&mut flatten_expression(arg.clone(), ctx)));
+
+    return flattened_ast;
+}
+
+fn calls_hex_namespace(expression: &FnCallExpr) -> bool {
+    let namespace = &expression.namespace;
+    namespace.path.len() == 1 && namespace.root() == "hex"
+}
+
+//`-x` and `+x` on values that are not literals, which rhai leaves as calls with one argument.
+//`-x` is `0 - x`, so integers get the same overflow check as rhai's negation
+fn flatten_unary(
+    name: &str,
+    arg: Expr,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let mut flattened_ast = flatten_expression(arg, ctx);
+
+    if name == "-" {
+        flattened_ast.insert(0, FlatNode::Op(Op::FnCall("-".to_string()), position));
+        flattened_ast.push(FlatNode::IntegerLiteral(0, position));
+    }
 
     return flattened_ast;
 }
@@ -244,8 +1133,15 @@ fn flatten_fn_call_expression(expression: FnCallExpr, position: Position) -> Vec
 #[derive(Debug)]
 pub enum Op {
     FnCall(String),
+    //a hex casting action, used as is
+    Action(String),
     Store(String),
     Push(String),
+    //calls the user-defined function stored in the variable
+    Call(String),
+    //jumps to the continuation stored in the variable
+    Return(String),
+    Pop,
 }
 
 #[derive(Debug)]
@@ -263,9 +1159,19 @@ pub enum FlatNode {
         block: Vec<FlatNode>,
         position: Position,
     },
+    Block(Vec<FlatNode>, Position),
     NumberLiteral(f64, Position),
+    //kept apart from other numbers so integer arithmetic can be told apart from float arithmetic
+    IntegerLiteral(INT, Position),
     BooleanLiteral(bool, Position),
     StringLiteral(String, Position),
     DynamicConstant(Box<Dynamic>, Position),
     Unit(Position),
+    //reads `.x`, `.y` or `.z`, a vector component or a map value depending on the inferred type
+    Property(String, Position),
+    //a method of both strings and lists such as `len`, with its number of arguments.
+    //the version for the inferred type of the receiver is used
+    Method(String, usize, Position),
+    //hexagon source from a `patterns` block, spliced in as is
+    Hex(Vec<AstNode>, Position),
 }
@@ -0,0 +1,514 @@
+use rhai::{ASTFlags, Expr, FnCallExpr, Ident, ImmutableString, Position};
+
+use crate::{
+    flatten_ast::{flatten_expression, FlatNode, FlattenContext, Op},
+    flatten_methods::{is_string_method, method, Method, MethodKind},
+    flatten_vector::VECTOR_COMPONENTS,
+};
+
+//object maps are lists of two lists, `[keys, values]`, where the value of `keys[i]` is `values[i]`.
+//literals list their keys in the same sorted order as rhai, but keys added by assignment
+//are appended, so `keys()` only matches rhai for keys that came from a literal.
+//reading a missing property gives null, like rhai's `()`
+
+//hidden variables used while a property is set, they are not live outside of map_set
+const MAP_KEY: &str = "map/key";
+const MAP_VALUE: &str = "map/value";
+const MAP_KEYS: &str = "map/keys";
+const MAP_VALUES: &str = "map/values";
+
+//holds the result of a mutating method while the changed receiver is written back
+const METHOD_RESULT: &str = "method/result";
+
+//one step of a chain like `a.b[1].keys()`, after its root
+enum Accessor {
+    Property(ImmutableString, Position),
+    Index(Expr, Position),
+    Method(FnCallExpr, Position),
+}
+
+impl Accessor {
+    fn position(&self) -> Position {
+        match self {
+            Accessor::Property(_, position)
+            | Accessor::Index(_, position)
+            | Accessor::Method(_, position) => *position,
+        }
+    }
+}
+
+/// Flattens a map literal. The flattened ast is in execution order.
+pub fn flatten_map(
+    entries: &[(Ident, Expr)],
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    //rhai maps are sorted by key, so the values are evaluated in that order too
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+    let mut flattened_ast = vec![];
+
+    for (key, _) in &entries {
+        flattened_ast.push(FlatNode::StringLiteral(key.name.to_string(), key.pos));
+    }
+    flattened_ast.push(FlatNode::NumberLiteral(entries.len() as f64, position));
+    flattened_ast.push(FlatNode::Op(
+        Op::FnCall("last_n_list".to_string()),
+        position,
+    ));
+
+    for (_, value) in &entries {
+        flattened_ast.extend(flatten_expression(value.clone(), ctx).into_iter().rev());
+    }
+    flattened_ast.push(FlatNode::NumberLiteral(entries.len() as f64, position));
+    flattened_ast.push(FlatNode::Op(
+        Op::FnCall("last_n_list".to_string()),
+        position,
+    ));
+
+    flattened_ast.append(&mut map_pair(position));
+
+    return flattened_ast;
+}
+
+//keys, values -> map.
+//the pair is built with the action itself rather than the `last_n_list` call of list literals,
+//so infer_types can tell maps from lists
+fn map_pair(position: Position) -> Vec<FlatNode> {
+    vec![
+        FlatNode::NumberLiteral(2.0, position),
+        FlatNode::Op(Op::Action("last_n_list".to_string()), position),
+    ]
+}
+
+/// Flattens a chain of property reads, indexing and method calls such as `a.b[1].keys()`.
+/// A mutating method like `a.b.push(x)` at the end of the chain writes the changed
+/// value back into the variable at its root. The flattened ast is in execution order.
+pub fn flatten_chain(expr: Expr, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    let (root, accessors) = match split_chain(expr) {
+        Ok(chain) => chain,
+        Err((message, position)) => {
+            ctx.error(message, position);
+            return vec![];
+        }
+    };
+
+    if let Some((Accessor::Method(call, position), path)) = accessors.split_last() {
+        let string_receiver = path.is_empty() && is_string(&root);
+        match method(&call.name, call.args.len(), string_receiver, *position) {
+            Some(method) if method.kind != MethodKind::Pure => {
+                return flatten_mutating_method(root, path, call, method, ctx)
+            }
+            _ => (),
+        }
+    }
+
+    let mut flattened_ast = flatten_expression(root.clone(), ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+
+    for (i, accessor) in accessors.iter().enumerate() {
+        match accessor {
+            Accessor::Method(call, position) => {
+                let string_receiver = i == 0 && is_string(&root);
+                let Some(method) = method(&call.name, call.args.len(), string_receiver, *position)
+                else {
+                    ctx.error(unknown_method(call), *position);
+                    return vec![];
+                };
+                if method.kind != MethodKind::Pure {
+                    ctx.error(
+                        "mutating methods must be called at the end of a chain",
+                        *position,
+                    );
+                    return vec![];
+                }
+
+                flattened_ast.append(&mut flatten_arguments(call, ctx));
+                if !string_receiver && is_string_method(&call.name, call.args.len()) {
+                    flattened_ast.push(FlatNode::Method(
+                        call.name.to_string(),
+                        call.args.len(),
+                        *position,
+                    ));
+                } else {
+                    flattened_ast.extend(method.flattened_ast);
+                }
+            }
+            Accessor::Property(name, position) if VECTOR_COMPONENTS.contains(&name.as_str()) => {
+                flattened_ast.push(FlatNode::Property(name.to_string(), *position))
+            }
+            accessor => {
+                flattened_ast.append(&mut push_key(accessor, ctx));
+                flattened_ast.append(&mut get(accessor));
+            }
+        }
+    }
+
+    return flattened_ast;
+}
+
+//the receiver is taken out of the variable at the root of the chain, changed, and put back
+fn flatten_mutating_method(
+    root: Expr,
+    path: &[Accessor],
+    call: &FnCallExpr,
+    method: Method,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let position = root.position();
+
+    //rhai changes a temporary copy, so the changed receiver is dropped
+    let Expr::Variable(ref var_data, _, _) = root else {
+        let mut flattened_ast = flatten_chain_path(root, path, ctx);
+        flattened_ast.append(&mut flatten_arguments(call, ctx));
+        flattened_ast.extend(method.flattened_ast);
+        if method.kind == MethodKind::MutatingWithResult {
+            flattened_ast.push(FlatNode::Op(Op::FnCall("swap".to_string()), position));
+            flattened_ast.push(FlatNode::Op(Op::Pop, position));
+        } else {
+            flattened_ast.push(FlatNode::Op(Op::Pop, position));
+            flattened_ast.push(FlatNode::Unit(position));
+        }
+        return flattened_ast;
+    };
+    let variable = ctx.variable(&var_data.3);
+
+    if let Some(Accessor::Method(_, position)) = path
+        .iter()
+        .find(|accessor| matches!(accessor, Accessor::Method(..)))
+    {
+        ctx.error("the result of a method call cannot be changed", *position);
+        return vec![];
+    }
+
+    let mut flattened_ast = vec![FlatNode::Op(Op::Push(variable.clone()), position)];
+    for accessor in path {
+        flattened_ast.append(&mut push_key(accessor, ctx));
+        flattened_ast.push(FlatNode::Op(
+            Op::FnCall("2dup".to_string()),
+            accessor.position(),
+        ));
+        flattened_ast.append(&mut get(accessor));
+    }
+
+    flattened_ast.append(&mut flatten_arguments(call, ctx));
+    flattened_ast.extend(method.flattened_ast);
+    if method.kind == MethodKind::MutatingWithResult {
+        flattened_ast.push(FlatNode::Op(Op::Store(METHOD_RESULT.to_string()), position));
+    }
+
+    for accessor in path.iter().rev() {
+        flattened_ast.append(&mut set(accessor));
+    }
+    flattened_ast.push(FlatNode::Op(Op::Store(variable), position));
+
+    if method.kind == MethodKind::MutatingWithResult {
+        flattened_ast.push(FlatNode::Op(Op::Push(METHOD_RESULT.to_string()), position));
+    } else {
+        flattened_ast.push(FlatNode::Unit(position));
+    }
+
+    return flattened_ast;
+}
+
+//reads the chain up to a mutating method, which cannot contain other methods
+fn flatten_chain_path(root: Expr, path: &[Accessor], ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    let mut flattened_ast = flatten_expression(root, ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+
+    for accessor in path {
+        if let Accessor::Method(_, position) = accessor {
+            ctx.error("the result of a method call cannot be changed", *position);
+            continue;
+        }
+        flattened_ast.append(&mut push_key(accessor, ctx));
+        flattened_ast.append(&mut get(accessor));
+    }
+
+    return flattened_ast;
+}
+
+fn flatten_arguments(call: &FnCallExpr, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    call.args
+        .iter()
+        .flat_map(|arg| flatten_expression(arg.clone(), ctx).into_iter().rev())
+        .collect()
+}
+
+fn unknown_method(call: &FnCallExpr) -> String {
+    format!(
+        "the method `{}` with {} arguments is not supported",
+        call.name,
+        call.args.len()
+    )
+}
+
+//literal strings are known to be strings before types are inferred
+fn is_string(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::StringConstant(..) | Expr::InterpolatedString(..)
+    )
+}
+
+/// Flattens an assignment to a property or an element such as `a.b[i] = x` or `a[i] += x`,
+/// writing the changed value back into the variable at the root of the chain.
+/// `op` is the operator of a compound assignment. The flattened ast is in execution order.
+pub fn flatten_chain_assignment(
+    lhs: Expr,
+    op: Option<String>,
+    value: Expr,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let (root, accessors) = match split_chain(lhs) {
+        Ok(chain) => chain,
+        Err((message, position)) => {
+            ctx.error(message, position);
+            return vec![];
+        }
+    };
+
+    let Expr::Variable(ref var_data, _, position) = root else {
+        ctx.error("only variables can be assigned to", root.position());
+        return vec![];
+    };
+    let variable = ctx.variable(&var_data.3);
+
+    if let Some(Accessor::Method(_, position)) = accessors
+        .iter()
+        .find(|accessor| matches!(accessor, Accessor::Method(..)))
+    {
+        ctx.error(
+            "the result of a method call cannot be assigned to",
+            *position,
+        );
+        return vec![];
+    }
+
+    //each value on the way to the target is kept on the stack below its key,
+    //then the values are put back together from the innermost outwards
+    let mut flattened_ast = vec![FlatNode::Op(Op::Push(variable.clone()), position)];
+
+    let (last, path) = accessors.split_last().unwrap();
+    for accessor in path {
+        flattened_ast.append(&mut push_key(accessor, ctx));
+        flattened_ast.push(FlatNode::Op(
+            Op::FnCall("2dup".to_string()),
+            accessor.position(),
+        ));
+        flattened_ast.append(&mut get(accessor));
+    }
+
+    flattened_ast.append(&mut push_key(last, ctx));
+    if let Some(op) = op {
+        flattened_ast.push(FlatNode::Op(
+            Op::FnCall("2dup".to_string()),
+            last.position(),
+        ));
+        flattened_ast.append(&mut get(last));
+        flattened_ast.extend(flatten_expression(value, ctx).into_iter().rev());
+        flattened_ast.push(FlatNode::Op(Op::FnCall(op), last.position()));
+    } else {
+        flattened_ast.extend(flatten_expression(value, ctx).into_iter().rev());
+    }
+
+    for accessor in accessors.iter().rev() {
+        flattened_ast.append(&mut set(accessor));
+    }
+    flattened_ast.push(FlatNode::Op(Op::Store(variable), position));
+
+    return flattened_ast;
+}
+
+//rhai nests the rest of a chain in the rhs of each `.` or `[]`, where the lhs is the
+//accessor of the parent. a `BREAK` flag marks an index that ends the chain, so the rhs
+//of `a[b[0]]` is the index `b[0]` and not the chain `[b][0]`
+fn split_chain(expr: Expr) -> Result<(Expr, Vec<Accessor>), (&'static str, Position)> {
+    match expr {
+        Expr::Dot(data, flags, position) => {
+            check_flags(flags, position)?;
+            Ok((data.lhs, accessors(data.rhs, false, flags)?))
+        }
+        Expr::Index(data, flags, position) => {
+            check_flags(flags, position)?;
+            Ok((data.lhs, accessors(data.rhs, true, flags)?))
+        }
+        expr => Err(("this expression is not supported yet", expr.position())),
+    }
+}
+
+fn accessors(
+    expr: Expr,
+    is_index: bool,
+    flags: ASTFlags,
+) -> Result<Vec<Accessor>, (&'static str, Position)> {
+    if flags.contains(ASTFlags::BREAK) {
+        return Ok(vec![accessor(expr, is_index)?]);
+    }
+
+    match expr {
+        Expr::Dot(data, flags, position) => {
+            check_flags(flags, position)?;
+            let mut chain = vec![accessor(data.lhs, is_index)?];
+            chain.append(&mut accessors(data.rhs, false, flags)?);
+            Ok(chain)
+        }
+        Expr::Index(data, flags, position) => {
+            check_flags(flags, position)?;
+            let mut chain = vec![accessor(data.lhs, is_index)?];
+            chain.append(&mut accessors(data.rhs, true, flags)?);
+            Ok(chain)
+        }
+        expr => Ok(vec![accessor(expr, is_index)?]),
+    }
+}
+
+fn accessor(expr: Expr, is_index: bool) -> Result<Accessor, (&'static str, Position)> {
+    match expr {
+        //`m["a"]` is the same as `m.a`
+        Expr::StringConstant(name, position) if is_index => Ok(Accessor::Property(name, position)),
+        expr if is_index => {
+            let position = expr.position();
+            Ok(Accessor::Index(expr, position))
+        }
+        Expr::Property(data, position) => Ok(Accessor::Property(data.2, position)),
+        Expr::MethodCall(call, position) => Ok(Accessor::Method(*call, position)),
+        expr => Err(("this property access is not supported yet", expr.position())),
+    }
+}
+
+fn check_flags(flags: ASTFlags, position: Position) -> Result<(), (&'static str, Position)> {
+    if flags.contains(ASTFlags::NEGATED) {
+        return Err(("`?.` and `?[` are not supported yet", position));
+    }
+
+    Ok(())
+}
+
+//pushes the key of a property or the index of an element,
+//which goes on top of the map or list it belongs to
+fn push_key(accessor: &Accessor, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    match accessor {
+        Accessor::Property(name, position) => {
+            vec![FlatNode::StringLiteral(name.to_string(), *position)]
+        }
+        Accessor::Index(index, position) => {
+            let mut flattened_ast = flatten_expression(index.clone(), ctx)
+                .into_iter()
+                .rev()
+                .collect::<Vec<_>>();
+            flattened_ast.append(&mut list_index(*position));
+            flattened_ast
+        }
+        Accessor::Method(..) => unreachable!("methods have no key"),
+    }
+}
+
+//map, key -> value or list, index -> element
+fn get(accessor: &Accessor) -> Vec<FlatNode> {
+    match accessor {
+        Accessor::Property(_, position) => map_get(*position),
+        Accessor::Index(_, position) => {
+            vec![FlatNode::Op(Op::FnCall("index".to_string()), *position)]
+        }
+        Accessor::Method(..) => unreachable!("methods are not read like values"),
+    }
+}
+
+//map, key, value -> map or list, index, element -> list
+fn set(accessor: &Accessor) -> Vec<FlatNode> {
+    match accessor {
+        Accessor::Property(_, position) => map_set(*position),
+        Accessor::Index(_, position) => {
+            vec![FlatNode::Op(Op::FnCall("replace".to_string()), *position)]
+        }
+        Accessor::Method(..) => unreachable!("methods cannot be assigned to"),
+    }
+}
+
+//list, index -> list, index from the start.
+//like rhai, a negative index counts from the end of the list
+pub(crate) fn list_index(position: Position) -> Vec<FlatNode> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+
+    vec![
+        action("over"),
+        action("list_size"),
+        action("over"),
+        FlatNode::NumberLiteral(0.0, position),
+        action("<"),
+        action("swap"),
+        FlatNode::NumberLiteral(0.0, position),
+        action("if"),
+        action("+"),
+    ]
+}
+
+//map, key -> value
+/// Reads a property of the map on top of the stack, in execution order.
+pub(crate) fn map_property(name: &str, position: Position) -> Vec<FlatNode> {
+    let mut flattened_ast = vec![FlatNode::StringLiteral(name.to_string(), position)];
+    flattened_ast.append(&mut map_get(position));
+    flattened_ast
+}
+
+fn map_get(position: Position) -> Vec<FlatNode> {
+    [
+        "swap",
+        "splat",
+        "rotate_reverse",
+        "swap",
+        "index_of",
+        "index",
+    ]
+    .into_iter()
+    .map(|action| FlatNode::Op(Op::FnCall(action.to_string()), position))
+    .collect()
+}
+
+//map, key, value -> map with the key set to the value
+fn map_set(position: Position) -> Vec<FlatNode> {
+    let op = |op: Op| FlatNode::Op(op, position);
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+
+    vec![
+        op(Op::Store(MAP_VALUE.to_string())),
+        op(Op::Store(MAP_KEY.to_string())),
+        action("splat"),
+        op(Op::Store(MAP_VALUES.to_string())),
+        op(Op::Store(MAP_KEYS.to_string())),
+        //a new key is appended with a null value, which is then replaced
+        op(Op::Push(MAP_KEYS.to_string())),
+        op(Op::Push(MAP_KEY.to_string())),
+        action("index_of"),
+        FlatNode::NumberLiteral(0.0, position),
+        action("<"),
+        action("duplicate"),
+        op(Op::Push(MAP_KEYS.to_string())),
+        op(Op::Push(MAP_KEY.to_string())),
+        action("append"),
+        op(Op::Push(MAP_KEYS.to_string())),
+        action("if"),
+        op(Op::Store(MAP_KEYS.to_string())),
+        op(Op::Push(MAP_VALUES.to_string())),
+        action("const/null"),
+        action("append"),
+        op(Op::Push(MAP_VALUES.to_string())),
+        action("if"),
+        op(Op::Push(MAP_KEYS.to_string())),
+        op(Op::Push(MAP_KEY.to_string())),
+        action("index_of"),
+        op(Op::Push(MAP_VALUE.to_string())),
+        action("replace"),
+        op(Op::Push(MAP_KEYS.to_string())),
+        action("swap"),
+    ]
+    .into_iter()
+    .chain(map_pair(position))
+    .collect()
+}
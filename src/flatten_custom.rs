@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use hexagon::parser::{parse, AstNode};
+use rhai::{
+    CustomExpr, Dynamic, Engine, Expr, ImmutableString, ParseError, ParseErrorType, Position, Token,
+};
+
+use crate::{
+    flatten_ast::{FlatNode, FlattenContext, Op},
+    flatten_switch::source_offset,
+};
+
+//`hex` is taken by the namespace of hex casting actions, so the block has its own keyword
+const PATTERNS_KEYWORD: &str = "patterns";
+const PATTERN_KEYWORD: &str = "pattern";
+
+const DIRECTIONS: &[&str] = &[
+    "NORTH_EAST",
+    "EAST",
+    "SOUTH_EAST",
+    "SOUTH_WEST",
+    "WEST",
+    "NORTH_WEST",
+];
+
+/// Registers the escape hatches for writing hex casting directly:
+///
+/// - `patterns { get_caster; entity_pos/eye }`, a block of hexagon source
+///   spliced into the program as is. Names rhai cannot read, like `Mind's Reflection`,
+///   are written as string literals: `patterns { "Mind's Reflection" }`
+/// - `pattern!("qaq", EAST)`, the pattern with the given angle signature
+///
+/// Both leave the stack as the patterns do, so as expressions they should push exactly one iota.
+/// rhai only checks that the block is made of tokens it can read.
+pub fn register_custom_syntax(engine: &mut Engine) {
+    engine.register_custom_syntax_with_state_raw(
+        PATTERNS_KEYWORD,
+        parse_patterns_block,
+        false,
+        |_, _, _| Ok(Dynamic::UNIT),
+    );
+
+    engine
+        .register_custom_syntax(
+            [PATTERN_KEYWORD, "!", "(", "$string$", ",", "$ident$", ")"],
+            false,
+            |_, _| Ok(Dynamic::UNIT),
+        )
+        .expect("the `pattern!` syntax is valid");
+}
+
+//accepts any tokens up to the `}` closing the block,
+//the state is how deeply braces are nested once the block is opened
+fn parse_patterns_block(
+    symbols: &[ImmutableString],
+    look_ahead: &str,
+    state: &mut Dynamic,
+) -> Result<Option<ImmutableString>, ParseError> {
+    if symbols.len() == 1 {
+        return Ok(Some("{".into()));
+    }
+
+    let depth = state.as_int().unwrap_or(1);
+    if depth == 0 {
+        return Ok(None);
+    }
+
+    let required = match look_ahead {
+        "{EOF}" => {
+            return Err(ParseErrorType::MissingToken(
+                "}".to_string(),
+                format!("to close the `{PATTERNS_KEYWORD}` block"),
+            )
+            .into_err(Position::NONE))
+        }
+        _ if look_ahead.starts_with('"') => "$string$",
+        _ if look_ahead.parse::<i32>().is_ok() => "$int$",
+        _ if look_ahead.parse::<f64>().is_ok() => "$float$",
+        //symbols, keywords and identifiers are matched as they are
+        _ => look_ahead,
+    };
+
+    *state = Dynamic::from(match required {
+        "{" | "#{" => depth + 1,
+        "}" => depth - 1,
+        _ => depth,
+    });
+
+    Ok(Some(required.into()))
+}
+
+pub fn flatten_custom(
+    custom: &CustomExpr,
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    match custom.tokens.first().map(ImmutableString::as_str) {
+        Some(PATTERNS_KEYWORD) => flatten_patterns_block(position, ctx),
+        Some(PATTERN_KEYWORD) => flatten_pattern(&custom.inputs, position, ctx),
+        _ => {
+            ctx.error("this custom syntax is not supported", position);
+            vec![]
+        }
+    }
+}
+
+fn flatten_patterns_block(position: Position, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    let Some(source) = patterns_block_source(&ctx.source, position) else {
+        ctx.error(
+            format!("could not read the `{PATTERNS_KEYWORD}` block"),
+            position,
+        );
+        return vec![];
+    };
+
+    match parse(&source, "", HashMap::new()) {
+        Ok(AstNode::Program(nodes)) => vec![FlatNode::Hex(nodes, position)],
+        Ok(node) => vec![FlatNode::Hex(vec![node], position)],
+        Err(err) => {
+            ctx.error(format!("invalid hex casting: {err:?}"), position);
+            vec![]
+        }
+    }
+}
+
+//the text between the braces of the block, with `;` between patterns turned into line breaks
+//and string literals replaced by their contents
+fn patterns_block_source(source: &str, position: Position) -> Option<String> {
+    let text = source.get(source_offset(source, position)?..)?;
+
+    let engine = Engine::new_raw();
+    let input = [text];
+    let (tokens, _) = engine.lex(&input);
+    let mut tokens = tokens.map(|(token, position)| (token, source_offset(text, position)));
+
+    let (token, _) = tokens.next()?;
+    if token.to_string() != PATTERNS_KEYWORD {
+        return None;
+    }
+    let (Token::LeftBrace, Some(open)) = tokens.next()? else {
+        return None;
+    };
+
+    let mut depth = 0;
+    let mut edits = vec![];
+    for (token, offset) in tokens {
+        match token {
+            Token::LeftBrace | Token::MapStart => depth += 1,
+            Token::RightBrace if depth == 0 => {
+                let mut block = text.get(open + 1..offset?)?.to_string();
+                //applied from the end so the offsets of earlier edits stay valid
+                for (range, replacement) in edits.into_iter().rev() {
+                    block.replace_range(range.start - open - 1..range.end - open - 1, &replacement);
+                }
+                return Some(block);
+            }
+            Token::RightBrace => depth -= 1,
+            Token::SemiColon => {
+                let offset = offset?;
+                edits.push((offset..offset + 1, "\n".to_string()));
+            }
+            Token::StringConstant(value) => {
+                let offset = offset?;
+                edits.push((offset..string_literal_end(text, offset)?, value.to_string()));
+            }
+            Token::EOF => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+//the offset just past the string literal starting at `start`
+fn string_literal_end(text: &str, start: usize) -> Option<usize> {
+    let mut chars = text.get(start + 1..)?.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return Some(start + 1 + index + 1),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+//the pattern is looked up by its signature, the start direction only changes how it is drawn
+fn flatten_pattern(inputs: &[Expr], position: Position, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    let (Some(Expr::StringConstant(signature, _)), Some(Expr::Variable(direction, _, _))) =
+        (inputs.first(), inputs.get(1))
+    else {
+        ctx.error("expected a signature and a start direction", position);
+        return vec![];
+    };
+
+    if !DIRECTIONS.contains(&direction.3.as_str()) {
+        ctx.error(
+            format!(
+                "unknown start direction `{}`, expected one of {}",
+                direction.3,
+                DIRECTIONS.join(", ")
+            ),
+            position,
+        );
+        return vec![];
+    }
+
+    match ctx.pattern_with_signature(signature) {
+        Some(pattern) => vec![FlatNode::Op(Op::Action(pattern), position)],
+        None => {
+            ctx.error(
+                format!("no hex casting pattern has the signature \"{signature}\""),
+                position,
+            );
+            vec![]
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use rhai::{Expr, Position};
+
+use crate::{
+    diagnostic::did_you_mean,
+    flatten_ast::{FlatNode, FlattenContext},
+};
+
+/// Flattens `entity("name")`, which embeds an entity declared in the project config.
+/// The name has to be a string literal, so the entity is known when compiling.
+pub fn flatten_entity(
+    args: &[Expr],
+    position: Position,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let [Expr::StringConstant(name, _)] = args else {
+        ctx.error(
+            "`entity` takes the name of an entity from the config as a string literal",
+            position,
+        );
+        return vec![];
+    };
+
+    let embed = match ctx.entities.get(name.as_str()) {
+        Some(Ok(embed)) => Ok(embed.clone()),
+        Some(Err(err)) => Err(format!("the entity `{name}` cannot be embedded: {err}")),
+        None => Err(
+            match did_you_mean(name, ctx.entities.keys().map(String::as_str)) {
+                Some(suggestion) => {
+                    format!("unknown entity `{name}`, did you mean `{suggestion}`?")
+                }
+                None => format!("unknown entity `{name}`, entities are declared in the config"),
+            },
+        ),
+    };
+
+    match embed {
+        Ok(embed) => vec![FlatNode::Hex(embed, position)],
+        Err(message) => {
+            ctx.error(message, position);
+            vec![]
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use rhai::Position;
+
+use crate::{
+    flatten_ast::{FlatNode, Op},
+    flatten_chain::list_index,
+};
+
+//how a method leaves the stack, starting from the receiver followed by the arguments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MethodKind {
+    //receiver, args -> result
+    Pure,
+    //receiver, args -> changed receiver, and the method returns `()`
+    Mutating,
+    //receiver, args -> changed receiver, result
+    MutatingWithResult,
+}
+
+#[derive(Debug)]
+pub struct Method {
+    pub kind: MethodKind,
+    pub flattened_ast: Vec<FlatNode>,
+}
+
+/// Whether strings have their own version of the method, in which case it is chosen
+/// once the type of the receiver is inferred.
+pub fn is_string_method(name: &str, arity: usize) -> bool {
+    method(name, arity, true, Position::NONE).is_some()
+}
+
+/// Looks up the builtin rhai method `name` taking `arity` arguments besides the receiver.
+/// The flattened ast is in execution order and runs after the receiver and arguments are pushed.
+pub fn method(
+    name: &str,
+    arity: usize,
+    string_receiver: bool,
+    position: Position,
+) -> Option<Method> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+    let number = |number: f64| FlatNode::NumberLiteral(number, position);
+
+    let len = if string_receiver {
+        "string/len"
+    } else {
+        "list_size"
+    };
+
+    let (kind, flattened_ast) = match (name, arity, string_receiver) {
+        ("len", 0, _) => (MethodKind::Pure, vec![action(len)]),
+        ("is_empty", 0, _) => (
+            MethodKind::Pure,
+            vec![action(len), number(0.0), action("==")],
+        ),
+
+        ("contains", 1, false) => (
+            MethodKind::Pure,
+            vec![action("index_of"), number(0.0), action(">=")],
+        ),
+        ("index_of", 1, false) => (MethodKind::Pure, vec![action("index_of")]),
+
+        //maps, see flatten_chain
+        ("keys", 0, false) => (MethodKind::Pure, vec![number(0.0), action("index")]),
+        ("values", 0, false) => (MethodKind::Pure, vec![number(1.0), action("index")]),
+
+        ("push", 1, false) => (MethodKind::Mutating, vec![action("append")]),
+        ("append", 1, false) => (MethodKind::Mutating, vec![action("add")]),
+        ("reverse", 0, false) => (MethodKind::Mutating, vec![action("reverse")]),
+        ("clear", 0, false) => (
+            MethodKind::Mutating,
+            vec![FlatNode::Op(Op::Pop, position), action("empty_list")],
+        ),
+
+        ("pop", 0, false) => (MethodKind::MutatingWithResult, vec![action("unappend")]),
+        //list -> rest of the list, first element
+        ("shift", 0, false) => (
+            MethodKind::MutatingWithResult,
+            vec![
+                action("duplicate"),
+                number(0.0),
+                action("index"),
+                action("swap"),
+                action("duplicate"),
+                action("list_size"),
+                number(1.0),
+                action("swap"),
+                action("slice"),
+                action("swap"),
+            ],
+        ),
+        //like indexing, a negative index counts from the end
+        ("remove", 1, false) => {
+            let mut flattened_ast = list_index(position);
+            flattened_ast.extend([
+                action("2dup"),
+                action("index"),
+                action("rotate_reverse"),
+                action("list_remove"),
+                action("swap"),
+            ]);
+            (MethodKind::MutatingWithResult, flattened_ast)
+        }
+
+        _ => return None,
+    };
+
+    Some(Method {
+        kind,
+        flattened_ast,
+    })
+}
@@ -0,0 +1,83 @@
+use rhai::Position;
+
+use crate::flatten_ast::{FlatNode, Op};
+
+//functions from rhai's standard packages, which are compile errors unless `std_function` maps them.
+//anything else that is not a user-defined function is assumed to be a hex casting action
+#[rustfmt::skip]
+pub const RHAI_STD_FUNCTIONS: &[&str] = &[
+    //math
+    "abs", "sign", "floor", "ceiling", "round", "int_part", "fraction", "sqrt", "exp", "ln", "log",
+    "sin", "cos", "tan", "asin", "acos", "atan", "sinh", "cosh", "tanh", "asinh", "acosh", "atanh",
+    "hypot", "to_degrees", "to_radians", "min", "max", "PI", "E", "is_nan", "is_finite",
+    "is_infinite", "is_zero", "is_odd", "is_even",
+    //conversions
+    "to_int", "to_float", "to_decimal", "parse_int", "parse_float", "to_string", "to_debug",
+    "to_char", "to_binary", "to_octal", "to_hex",
+    //core
+    "debug", "type_of", "range", "sleep", "timestamp",
+];
+
+pub fn is_std_function(name: &str) -> bool {
+    RHAI_STD_FUNCTIONS.contains(&name)
+}
+
+/// Maps a function from rhai's standard library to hex casting actions, if there is an equivalent.
+/// The flattened ast is in execution order and runs after the arguments are pushed.
+pub fn std_function(name: &str, arity: usize, position: Position) -> Option<Vec<FlatNode>> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+    let number = |number: f64| FlatNode::NumberLiteral(number, position);
+
+    let flattened_ast = match (name, arity) {
+        ("abs", 1) => vec![action("abs")],
+        //Axial Purification gives the sign of a number
+        ("sign", 1) => vec![action("coerce_axial")],
+        ("floor", 1) => vec![action("floor")],
+        ("ceiling", 1) => vec![action("ceil")],
+        //rhai rounds halfway cases away from zero, so the absolute value is rounded
+        ("round", 1) => vec![
+            action("duplicate"),
+            action("coerce_axial"),
+            action("swap"),
+            action("abs"),
+            number(0.5),
+            action("+"),
+            action("floor"),
+            action("*"),
+        ],
+
+        ("sqrt", 1) => vec![number(0.5), action("**")],
+        ("exp", 1) => vec![action("const/double/e"), action("swap"), action("**")],
+        ("ln", 1) => vec![action("const/double/e"), action("logarithm")],
+        ("log", 1) => vec![number(10.0), action("logarithm")],
+        ("log", 2) => vec![action("logarithm")],
+
+        ("sin", 1) => vec![action("sin")],
+        ("cos", 1) => vec![action("cos")],
+        ("tan", 1) => vec![action("tan")],
+        ("asin", 1) => vec![action("arcsin")],
+        ("acos", 1) => vec![action("arccos")],
+        ("atan", 1) => vec![action("arctan")],
+
+        //a, b -> a, b, a < b -> a < b, a, b -> the smaller one
+        ("min", 2) => vec![
+            action("2dup"),
+            action("<"),
+            action("rotate_reverse"),
+            action("if"),
+        ],
+        ("max", 2) => vec![
+            action("2dup"),
+            action(">"),
+            action("rotate_reverse"),
+            action("if"),
+        ],
+
+        ("PI", 0) => vec![action("const/double/pi")],
+        ("E", 0) => vec![action("const/double/e")],
+
+        _ => return None,
+    };
+
+    Some(flattened_ast)
+}
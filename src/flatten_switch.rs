@@ -0,0 +1,340 @@
+use std::iter::Peekable;
+
+use rhai::{Engine, Expr, Position, RangeCase, Stmt, SwitchCasesCollection, Token, INT};
+
+use crate::flatten_ast::{
+    flatten_expression, flatten_statements, flatten_value_block, FlatNode, FlattenContext, Op,
+};
+
+//rhai only keeps the hashes of literal switch case values, and unrolls small ranges
+//into literal cases, so these are read back from the tokens of each case
+#[derive(Debug, Default)]
+struct CaseArm {
+    literals: Vec<CaseLiteral>,
+    ranges: Vec<(INT, INT, bool)>,
+}
+
+#[derive(Debug)]
+enum CaseLiteral {
+    Number(f64),
+    String(String),
+    Boolean(bool),
+}
+
+/// Flattens a switch into a chain of if blocks, testing literal cases first and
+/// range cases second like rhai does. `keep_value` leaves the value of the
+/// matching case on the stack, for switches used as expressions.
+pub fn flatten_switch(
+    data: &(Expr, SwitchCasesCollection),
+    position: Position,
+    keep_value: bool,
+    ctx: &mut FlattenContext,
+) -> Vec<FlatNode> {
+    let (expr, cases) = data;
+
+    //only literal cases need the source, rhai keeps large ranges as they are
+    let mut arms = if cases.cases.is_empty() {
+        cases
+            .expressions
+            .iter()
+            .map(|_| CaseArm::default())
+            .collect()
+    } else {
+        match case_arms(&ctx.source, position) {
+            Some(arms) if arms.len() == cases.expressions.len() => arms,
+            _ => {
+                ctx.error(
+                    "switch cases must be literals, ranges of integers or `_`",
+                    position,
+                );
+                return vec![];
+            }
+        }
+    };
+
+    for range in cases.ranges.iter() {
+        let range_case = match range {
+            RangeCase::ExclusiveInt(range, _) => (range.start, range.end, false),
+            RangeCase::InclusiveInt(range, _) => (*range.start(), *range.end(), true),
+        };
+
+        //ranges read from the source are already there
+        let arm = &mut arms[range.index()];
+        if !arm.ranges.contains(&range_case) {
+            arm.ranges.push(range_case);
+        }
+    }
+
+    let id = ctx.unique_id();
+    let value = format!("switch/{id}/value");
+
+    let mut branches = vec![];
+    for (index, arm) in arms.iter().enumerate() {
+        if !arm.literals.is_empty() {
+            let tests = arm
+                .literals
+                .iter()
+                .map(|literal| literal_test(&value, literal, position))
+                .collect();
+            branches.push((any(tests, position), index));
+        }
+    }
+    for (index, arm) in arms.iter().enumerate() {
+        if !arm.ranges.is_empty() {
+            let tests = arm
+                .ranges
+                .iter()
+                .map(|range| range_test(&value, *range, position))
+                .collect();
+            branches.push((any(tests, position), index));
+        }
+    }
+
+    let mut chain = match cases.def_case {
+        Some(index) => case_action(&cases.expressions[index].expr, keep_value, ctx),
+        None if keep_value => vec![FlatNode::Unit(position)],
+        None => vec![],
+    };
+
+    for (test, index) in branches.into_iter().rev() {
+        let case = &cases.expressions[index];
+
+        let condition = match case.condition {
+            Expr::BoolConstant(true, _) => test,
+            ref guard => vec![
+                FlatNode::IfBlock {
+                    condition: test,
+                    succeed: flatten_expression(guard.clone(), ctx)
+                        .into_iter()
+                        .rev()
+                        .collect(),
+                    fail: Some(vec![FlatNode::BooleanLiteral(false, position)]),
+                    position,
+                },
+                FlatNode::Op(Op::FnCall("eval".to_string()), position),
+            ],
+        };
+
+        chain = vec![
+            FlatNode::IfBlock {
+                condition,
+                succeed: case_action(&case.expr, keep_value, ctx),
+                fail: Some(chain),
+                position,
+            },
+            FlatNode::Op(Op::FnCall("eval".to_string()), position),
+        ];
+    }
+
+    let mut flattened_ast = flatten_expression(expr.clone(), ctx)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+    flattened_ast.push(FlatNode::Op(Op::Store(value), position));
+    flattened_ast.append(&mut chain);
+
+    //built in order, but statements and expressions are flattened in reverse
+    flattened_ast.reverse();
+    return flattened_ast;
+}
+
+fn case_action(expr: &Expr, keep_value: bool, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    match (expr, keep_value) {
+        (Expr::Stmt(block), true) => flatten_value_block(block.statements(), block.position(), ctx),
+        (Expr::Stmt(block), false) => flatten_statements(block.statements(), ctx),
+        (expr, true) => flatten_expression(expr.clone(), ctx)
+            .into_iter()
+            .rev()
+            .collect(),
+        (expr, false) => flatten_statements(&[Stmt::Expr(Box::new(expr.clone()))], ctx),
+    }
+}
+
+fn literal_test(value: &str, literal: &CaseLiteral, position: Position) -> Vec<FlatNode> {
+    let literal = match literal {
+        CaseLiteral::Number(number) => FlatNode::NumberLiteral(*number, position),
+        CaseLiteral::String(string) => FlatNode::StringLiteral(string.clone(), position),
+        CaseLiteral::Boolean(bool) => FlatNode::BooleanLiteral(*bool, position),
+    };
+
+    vec![
+        FlatNode::Op(Op::Push(value.to_string()), position),
+        literal,
+        FlatNode::Op(Op::FnCall("==".to_string()), position),
+    ]
+}
+
+fn range_test(
+    value: &str,
+    (start, end, inclusive): (INT, INT, bool),
+    position: Position,
+) -> Vec<FlatNode> {
+    let end_comparison = if inclusive { "<=" } else { "<" };
+
+    vec![
+        FlatNode::Op(Op::Push(value.to_string()), position),
+        FlatNode::NumberLiteral(start as f64, position),
+        FlatNode::Op(Op::FnCall(">=".to_string()), position),
+        FlatNode::Op(Op::Push(value.to_string()), position),
+        FlatNode::NumberLiteral(end as f64, position),
+        FlatNode::Op(Op::FnCall(end_comparison.to_string()), position),
+        FlatNode::Op(Op::FnCall("and".to_string()), position),
+    ]
+}
+
+fn any(tests: Vec<Vec<FlatNode>>, position: Position) -> Vec<FlatNode> {
+    let mut flattened_ast = vec![];
+
+    for (i, mut test) in tests.into_iter().enumerate() {
+        flattened_ast.append(&mut test);
+        if i > 0 {
+            flattened_ast.push(FlatNode::Op(Op::FnCall("or".to_string()), position));
+        }
+    }
+
+    return flattened_ast;
+}
+
+pub(crate) fn source_offset(source: &str, position: Position) -> Option<usize> {
+    let (line, column) = (position.line()?, position.position()?);
+
+    let line_start = source
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum::<usize>();
+    let column_offset = source.get(line_start..)?.char_indices().nth(column - 1)?.0;
+
+    Some(line_start + column_offset)
+}
+
+fn case_arms(source: &str, position: Position) -> Option<Vec<CaseArm>> {
+    let text = source.get(source_offset(source, position)?..)?;
+
+    let engine = Engine::new_raw();
+    let input = [text];
+    let (tokens, _) = engine.lex(&input);
+    let mut tokens = tokens.map(|(token, _)| token).peekable();
+
+    //skip `switch` and the value being matched
+    if tokens.next()? != Token::Switch {
+        return None;
+    }
+    take_until(&mut tokens, |token| *token == Token::LeftBrace)?;
+
+    let mut arms = vec![];
+
+    loop {
+        if tokens.peek()? == &Token::RightBrace {
+            break;
+        }
+
+        let pattern = take_until(&mut tokens, |token| *token == Token::DoubleArrow)?;
+        arms.push(parse_pattern(pattern)?);
+
+        //skip the case's action, which is a block or an expression ending in `,` or `}`
+        if tokens.peek()? == &Token::LeftBrace {
+            tokens.next();
+            take_until(&mut tokens, |token| *token == Token::RightBrace)?;
+            if tokens.peek()? == &Token::Comma {
+                tokens.next();
+            }
+        } else {
+            let mut depth = 0;
+            loop {
+                match tokens.peek()? {
+                    Token::Comma if depth == 0 => {
+                        tokens.next();
+                        break;
+                    }
+                    Token::RightBrace if depth == 0 => break,
+                    Token::EOF => return None,
+                    token => {
+                        depth += nesting(token);
+                        tokens.next();
+                    }
+                }
+            }
+        }
+    }
+
+    Some(arms)
+}
+
+fn nesting(token: &Token) -> i32 {
+    match token {
+        Token::LeftParen | Token::LeftBracket | Token::LeftBrace | Token::MapStart => 1,
+        Token::RightParen | Token::RightBracket | Token::RightBrace => -1,
+        _ => 0,
+    }
+}
+
+//takes tokens up to the first matching token outside of any brackets, consuming it
+fn take_until(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    end: impl Fn(&Token) -> bool,
+) -> Option<Vec<Token>> {
+    let mut taken = vec![];
+    let mut depth = 0;
+
+    loop {
+        let token = tokens.next()?;
+        if depth == 0 && end(&token) {
+            return Some(taken);
+        }
+        if token == Token::EOF {
+            return None;
+        }
+        depth += nesting(&token);
+        taken.push(token);
+    }
+}
+
+fn parse_pattern(pattern: Vec<Token>) -> Option<CaseArm> {
+    //an `if` guard is part of the case's condition expression
+    let values = match pattern.iter().position(|token| *token == Token::If) {
+        Some(guard) => &pattern[..guard],
+        None => &pattern[..],
+    };
+
+    let mut arm = CaseArm::default();
+
+    for value in values.split(|token| *token == Token::Pipe) {
+        match value {
+            [Token::Underscore] => (),
+            [Token::True] => arm.literals.push(CaseLiteral::Boolean(true)),
+            [Token::False] => arm.literals.push(CaseLiteral::Boolean(false)),
+            [Token::StringConstant(string)] => {
+                arm.literals.push(CaseLiteral::String(string.to_string()))
+            }
+            [Token::CharConstant(char)] => arm.literals.push(CaseLiteral::String(char.to_string())),
+            [Token::FloatConstant(float)] => arm.literals.push(CaseLiteral::Number(**float)),
+            [Token::UnaryMinus, Token::FloatConstant(float)] => {
+                arm.literals.push(CaseLiteral::Number(-**float))
+            }
+            value => {
+                let (start, rest) = parse_integer(value)?;
+                match rest {
+                    [] => arm.literals.push(CaseLiteral::Number(start as f64)),
+                    [Token::ExclusiveRange, end @ ..] => {
+                        arm.ranges.push((start, parse_integer(end)?.0, false))
+                    }
+                    [Token::InclusiveRange, end @ ..] => {
+                        arm.ranges.push((start, parse_integer(end)?.0, true))
+                    }
+                    _ => return None,
+                }
+            }
+        }
+    }
+
+    Some(arm)
+}
+
+fn parse_integer(tokens: &[Token]) -> Option<(INT, &[Token])> {
+    match tokens {
+        [Token::IntegerConstant(int), rest @ ..] => Some((*int, rest)),
+        [Token::UnaryMinus, Token::IntegerConstant(int), rest @ ..] => Some((-*int, rest)),
+        _ => None,
+    }
+}
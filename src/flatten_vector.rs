@@ -0,0 +1,61 @@
+use rhai::Position;
+
+use crate::{
+    flatten_ast::{FlatNode, Op},
+    flatten_chain::map_property,
+};
+
+//properties that read a component when the receiver is known to be a vector
+pub const VECTOR_COMPONENTS: &[&str] = &["x", "y", "z"];
+
+/// Maps the functions creating vectors to hex casting actions.
+/// The flattened ast is in execution order and runs after the arguments are pushed.
+pub fn vector_function(name: &str, arity: usize, position: Position) -> Option<Vec<FlatNode>> {
+    match (name, arity) {
+        ("vec", 3) => Some(vec![FlatNode::Op(
+            Op::FnCall("construct_vec".to_string()),
+            position,
+        )]),
+        _ => None,
+    }
+}
+
+/// Reads a component of the vector on top of the stack, in execution order.
+pub fn vector_component(name: &str, position: Position) -> Option<Vec<FlatNode>> {
+    let pop = || FlatNode::Op(Op::Pop, position);
+    let swap = || FlatNode::Op(Op::FnCall("swap".to_string()), position);
+    let deconstruct = FlatNode::Op(Op::FnCall("deconstruct_vec".to_string()), position);
+
+    //vector -> x, y, z
+    let flattened_ast = match name {
+        "x" => vec![deconstruct, pop(), pop()],
+        "y" => vec![deconstruct, pop(), swap(), pop()],
+        "z" => vec![deconstruct, swap(), pop(), swap(), pop()],
+        _ => return None,
+    };
+
+    Some(flattened_ast)
+}
+
+/// Reads `.x`, `.y` or `.z` from a value whose type is not known, in execution order.
+/// Maps are lists, so the value is checked to be a list at runtime and read as a vector otherwise.
+pub fn component_or_property(name: &str, position: Position) -> Vec<FlatNode> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+
+    vec![
+        FlatNode::IfBlock {
+            //value -> value, whether it is a list
+            condition: vec![
+                action("duplicate"),
+                action("type/iota"),
+                action("empty_list"),
+                action("type/iota"),
+                action("=="),
+            ],
+            succeed: map_property(name, position),
+            fail: Some(vector_component(name, position).unwrap_or_default()),
+            position,
+        },
+        action("eval"),
+    ]
+}
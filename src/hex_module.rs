@@ -0,0 +1,111 @@
+use std::{any::TypeId, collections::HashMap};
+
+use hexagon::pattern_registry::PatternRegistry;
+use rhai::{Dynamic, FnAccess, FnNamespace, Module};
+
+/// A hex casting action callable from rhai as `hex::name(...)`.
+#[derive(Debug, Clone)]
+pub struct HexAction {
+    /// The internal name of the pattern in the registry.
+    pub pattern: String,
+    /// How many iotas the action takes.
+    pub arity: usize,
+    /// How many iotas the action pushes.
+    pub results: usize,
+}
+
+//hexagon's registry does not know how many iotas a pattern takes or pushes,
+//so only the patterns in this table can be called through the `hex` namespace.
+//actions pushing a number of iotas only known at runtime, like splat, are left out
+#[rustfmt::skip]
+const ARITIES: &[(&str, usize, usize)] = &[
+    //basic patterns
+    ("get_caster", 0, 1), ("entity_pos/eye", 1, 1), ("entity_pos/foot", 1, 1),
+    ("get_entity_look", 1, 1), ("get_entity_height", 1, 1), ("get_entity_velocity", 1, 1),
+    ("raycast", 2, 1), ("raycast/axis", 2, 1), ("raycast/entity", 2, 1),
+    ("circle/impetus_pos", 0, 1), ("circle/impetus_dir", 0, 1), ("circle/bounds/min", 0, 1),
+    ("circle/bounds/max", 0, 1), ("print", 1, 1),
+    //reading and writing
+    ("read", 0, 1), ("write", 1, 0), ("readable", 0, 1), ("writable", 0, 1),
+    ("read/entity", 1, 1), ("readable/entity", 1, 1), ("read/local", 0, 1), ("write/local", 1, 0),
+    //math
+    ("add", 2, 1), ("sub", 2, 1), ("mul_dot", 2, 1), ("div_cross", 2, 1), ("abs", 1, 1),
+    ("pow_proj", 2, 1), ("floor", 1, 1), ("ceil", 1, 1), ("modulo", 2, 1),
+    ("construct_vec", 3, 1), ("deconstruct_vec", 1, 3), ("coerce_axial", 1, 1), ("sin", 1, 1),
+    ("cos", 1, 1), ("tan", 1, 1), ("arcsin", 1, 1), ("arccos", 1, 1), ("arctan", 1, 1),
+    ("logarithm", 2, 1), ("random", 0, 1),
+    //logic
+    ("and", 2, 1), ("or", 2, 1), ("xor", 2, 1), ("not", 1, 1), ("equals", 2, 1),
+    ("not_equals", 2, 1), ("greater", 2, 1), ("less", 2, 1), ("greater_eq", 2, 1),
+    ("less_eq", 2, 1),
+    //entities
+    ("get_entity", 1, 1), ("get_entity/animal", 1, 1), ("get_entity/monster", 1, 1),
+    ("get_entity/item", 1, 1), ("get_entity/player", 1, 1), ("get_entity/living", 1, 1),
+    ("zone_entity", 2, 1), ("zone_entity/animal", 2, 1), ("zone_entity/monster", 2, 1),
+    ("zone_entity/item", 2, 1), ("zone_entity/player", 2, 1), ("zone_entity/living", 2, 1),
+    //lists
+    ("index", 2, 1), ("slice", 3, 1), ("append", 2, 1), ("list_size", 1, 1), ("singleton", 1, 1),
+    ("empty_list", 0, 1), ("reverse", 1, 1), ("index_of", 2, 1), ("list_remove", 2, 1),
+    ("replace", 3, 1), ("construct", 2, 1), ("deconstruct", 1, 2), ("unappend", 1, 2),
+    //spells
+    ("explode", 2, 0), ("explode/fire", 2, 0), ("add_motion", 2, 0), ("blink", 2, 0),
+    ("break_block", 1, 0), ("place_block", 1, 0), ("colorize", 0, 0), ("create_water", 1, 0),
+    ("destroy_water", 1, 0), ("ignite", 1, 0), ("extinguish", 1, 0), ("conjure_block", 1, 0),
+    ("conjure_light", 1, 0), ("bonemeal", 1, 0), ("recharge", 1, 0), ("erase", 0, 0),
+    ("edify", 1, 0), ("beep", 3, 0), ("potion/weakness", 3, 0), ("potion/levitation", 2, 0),
+    ("potion/wither", 3, 0), ("potion/poison", 3, 0), ("potion/slowness", 3, 0),
+    ("sentinel/create", 1, 0), ("sentinel/destroy", 0, 0), ("sentinel/get_pos", 0, 1),
+    ("sentinel/wayfind", 1, 1),
+];
+
+//patterns like `entity_pos/eye` are called as `hex::entity_pos_eye`
+fn rhai_name(pattern: &str) -> Option<String> {
+    let name = pattern.replace('/', "_");
+
+    let is_identifier = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+    is_identifier.then_some(name)
+}
+
+/// Collects the actions of the patterns in the registry that are known to the `hex` namespace,
+/// keyed by their rhai name.
+pub fn hex_actions(pattern_registry: &PatternRegistry) -> HashMap<String, HexAction> {
+    let arities = ARITIES
+        .iter()
+        .map(|(pattern, arity, results)| (*pattern, (*arity, *results)))
+        .collect::<HashMap<_, _>>();
+
+    pattern_registry
+        .iter()
+        .filter_map(|pattern| {
+            let name = rhai_name(&pattern.internal_name)?;
+            let (arity, results) = *arities.get(pattern.internal_name.as_str())?;
+            let action = HexAction {
+                pattern: pattern.internal_name.clone(),
+                arity,
+                results,
+            };
+            Some((name, action))
+        })
+        .collect()
+}
+
+/// Builds the `hex` module registered with the rhai engine, so scripts calling hex casting
+/// actions parse with strict variables and can be run by rhai. The functions are stubs
+/// that return `()`, hex casting actions only run once the script is compiled.
+pub fn hex_module(actions: &HashMap<String, HexAction>) -> Module {
+    let mut module = Module::new();
+
+    for (name, action) in actions {
+        module.set_raw_fn(
+            name,
+            FnNamespace::Internal,
+            FnAccess::Public,
+            vec![TypeId::of::<Dynamic>(); action.arity],
+            |_, _| Ok(Dynamic::UNIT),
+        );
+    }
+
+    module
+}
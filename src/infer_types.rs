@@ -0,0 +1,732 @@
+use std::collections::HashMap;
+
+use rhai::{Dynamic, Position};
+
+use crate::{
+    flatten_ast::{FlatNode, FlattenContext, Op},
+    flatten_methods::method,
+    flatten_vector::{component_or_property, vector_component},
+    vector::Vector,
+};
+
+//what is known about a value on the stack or in a variable
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Array,
+    Map,
+    Vector,
+    Entity,
+    Unit,
+    #[default]
+    Unknown,
+    //not given a value by any walk yet, like the arguments of a function no call to is seen yet.
+    //it joins to whatever the value turns out to be, and is unknown once the walks are done
+    Unassigned,
+}
+
+impl Type {
+    //the names given by rhai's `type_of`, which are also used for type hints
+    #[rustfmt::skip]
+    const NAMES: &'static [(&'static str, Type)] = &[
+        ("i32", Type::Int), ("f64", Type::Float), ("bool", Type::Bool), ("string", Type::String),
+        ("array", Type::Array), ("map", Type::Map), ("vector", Type::Vector),
+        ("entity", Type::Entity), ("()", Type::Unit),
+    ];
+
+    fn from_name(name: &str) -> Option<Type> {
+        Self::NAMES
+            .iter()
+            .find(|(type_name, _)| *type_name == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, value)| *value == self)
+            .map_or("?", |(type_name, _)| type_name)
+    }
+
+    fn of_constant(value: &Dynamic) -> Type {
+        if value.is_int() {
+            Type::Int
+        } else if value.is_float() {
+            Type::Float
+        } else if value.is_bool() {
+            Type::Bool
+        } else if value.is_string() || value.is_char() {
+            Type::String
+        } else if value.is_array() {
+            Type::Array
+        } else if value.is_map() {
+            Type::Map
+        } else if value.is_unit() {
+            Type::Unit
+        } else if value.is::<Vector>() {
+            Type::Vector
+        } else {
+            Type::Unknown
+        }
+    }
+
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Unassigned, value) | (value, Type::Unassigned) => value,
+            (a, b) if a == b => a,
+            _ => Type::Unknown,
+        }
+    }
+
+    fn is_known(self) -> bool {
+        !matches!(self, Type::Unknown | Type::Unassigned)
+    }
+
+    fn is_number(self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+}
+
+//the result of the hex casting actions the stack is followed through, with how many iotas they take
+#[rustfmt::skip]
+const ACTIONS: &[(&str, usize, Type)] = &[
+    ("get_caster", 0, Type::Entity), ("entity_pos/eye", 1, Type::Vector),
+    ("entity_pos/foot", 1, Type::Vector), ("get_entity_look", 1, Type::Vector),
+    ("get_entity_height", 1, Type::Float), ("get_entity_velocity", 1, Type::Vector),
+    ("raycast", 2, Type::Vector), ("raycast/axis", 2, Type::Vector),
+    ("circle/impetus_pos", 0, Type::Vector), ("circle/impetus_dir", 0, Type::Vector),
+    ("construct_vec", 3, Type::Vector), ("random", 0, Type::Float),
+    ("list_size", 1, Type::Int), ("string/len", 1, Type::Int), ("index_of", 2, Type::Int),
+    ("index", 2, Type::Unknown), ("append", 2, Type::Array), ("replace", 3, Type::Array),
+    ("list_remove", 2, Type::Array), ("slice", 3, Type::Array), ("reverse", 1, Type::Array),
+    ("empty_list", 0, Type::Array), ("string/add", 2, Type::String),
+    ("string/iota", 1, Type::String),
+];
+
+//the stack as far as it is known, popping past its bottom gives `below`
+#[derive(Debug, Clone, Default)]
+struct Stack {
+    values: Vec<Type>,
+    below: Type,
+}
+
+impl Stack {
+    fn push(&mut self, value: Type) {
+        self.values.push(value)
+    }
+
+    fn pop(&mut self) -> Type {
+        self.values.pop().unwrap_or(self.below)
+    }
+
+    //after an action with an unknown effect nothing below the top can be trusted
+    fn clear(&mut self) {
+        self.values.clear();
+        self.below = Type::Unknown;
+    }
+
+    fn join(&mut self, other: &Stack) {
+        if self.values.len() == other.values.len() {
+            for (value, other) in self.values.iter_mut().zip(&other.values) {
+                *value = value.join(*other);
+            }
+            self.below = self.below.join(other.below);
+        } else {
+            self.clear()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inference<'a> {
+    //joined over every store, since variables can change in loops and functions
+    variables: HashMap<String, Type>,
+    //variables declared with a type, which keep it whatever they are assigned
+    hints: HashMap<String, Type>,
+    //what is known about the stack user-defined functions are called with, joined over every call,
+    //by the variable holding the function
+    arguments: HashMap<String, Vec<Type>>,
+    changed: bool,
+    //set for the last walk, once the types of the variables are settled
+    rewrite: bool,
+    ctx: Option<&'a mut FlattenContext>,
+}
+
+/// Reads the type hint of a `let` statement, a `//: type` comment on the line of the variable:
+///
+/// ```text
+/// let target = raycast(eye, look); //: vector
+/// ```
+pub(crate) fn type_hint(source: &str, position: Position) -> Option<String> {
+    let line = source.lines().nth(position.line()? - 1)?;
+    let (_, hint) = line.split_once("//:")?;
+
+    hint.split_whitespace().next().map(str::to_string)
+}
+
+/// Follows the type of every value through the flattened ast, so that
+///
+/// - `.x`, `.y` and `.z` read the components of values known to be vectors and the
+///   properties of values known to be maps, other values are checked at runtime
+/// - `&` and `|` compile to the boolean or bitwise action when the operands are known,
+///   instead of choosing at runtime
+/// - `+ - * / **` on two integers get rhai's `i32` semantics: division truncates and
+///   results that do not fit in an `i32` are a mishap, like rhai's overflow errors.
+///   `%` already keeps the sign of the dividend in hex casting, as it does in rhai
+/// - operators applied to values they cannot take are reported as errors
+///
+/// Only what is known on every path is used, anything else keeps the runtime behaviour.
+pub fn infer_types(mut ast: Vec<FlatNode>, ctx: &mut FlattenContext) -> Vec<FlatNode> {
+    let mut hints = HashMap::new();
+    for (variable, hint, position) in std::mem::take(&mut ctx.type_hints) {
+        match Type::from_name(&hint) {
+            Some(value) => {
+                hints.insert(variable, value);
+            }
+            None => ctx.error(
+                format!(
+                    "unknown type `{hint}`, expected one of {}",
+                    Type::NAMES
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                position,
+            ),
+        }
+    }
+
+    let mut inference = Inference {
+        hints,
+        ..Inference::default()
+    };
+
+    loop {
+        inference.changed = false;
+        ast = inference.walk(ast, &mut Stack::default());
+        if !inference.changed {
+            break;
+        }
+    }
+
+    //what is still unassigned is only reached by values of calls that never happen
+    let unassigned = |value: &mut Type| {
+        if *value == Type::Unassigned {
+            *value = Type::Unknown;
+        }
+    };
+    inference.variables.values_mut().for_each(unassigned);
+    inference
+        .arguments
+        .values_mut()
+        .flatten()
+        .for_each(unassigned);
+
+    inference.rewrite = true;
+    inference.ctx = Some(ctx);
+    inference.walk(ast, &mut Stack::default())
+}
+
+impl Inference<'_> {
+    //errors are reported once, by the last walk
+    fn error(&mut self, message: impl Into<String>, position: Position) {
+        if let Some(ctx) = &mut self.ctx {
+            ctx.error(message, position)
+        }
+    }
+
+    fn variable(&self, name: &str) -> Type {
+        self.hints
+            .get(name)
+            .or_else(|| self.variables.get(name))
+            .copied()
+            .unwrap_or(Type::Unknown)
+    }
+
+    fn store(&mut self, name: &str, value: Type, position: Position) {
+        if let Some(hint) = self.hints.get(name).copied() {
+            if value.is_known() && value != hint {
+                self.error(
+                    format!(
+                        "the variable is declared as {} but is assigned {}",
+                        hint.name(),
+                        value.name()
+                    ),
+                    position,
+                );
+            }
+            return;
+        }
+
+        let joined = match self.variables.get(name) {
+            Some(current) => current.join(value),
+            None => value,
+        };
+
+        if self.variables.insert(name.to_string(), joined) != Some(joined) {
+            self.changed = true;
+        }
+    }
+
+    //only the top of the stack is kept, the arguments are pushed last
+    fn call(&mut self, function: &str, stack: &Stack) {
+        let arguments = match self.arguments.get(function) {
+            Some(current) => {
+                let len = current.len().min(stack.values.len());
+                current[current.len() - len..]
+                    .iter()
+                    .zip(&stack.values[stack.values.len() - len..])
+                    .map(|(current, value)| current.join(*value))
+                    .collect()
+            }
+            None => stack.values.clone(),
+        };
+
+        if self.arguments.get(function) != Some(&arguments) {
+            self.arguments.insert(function.to_string(), arguments);
+            self.changed = true;
+        }
+    }
+
+    fn walk(&mut self, nodes: Vec<FlatNode>, stack: &mut Stack) -> Vec<FlatNode> {
+        let mut walked = vec![];
+        let mut nodes = nodes.into_iter().peekable();
+
+        while let Some(node) = nodes.next() {
+            match node {
+                FlatNode::Op(op, position) => walked.extend(self.op(op, position, stack)),
+
+                FlatNode::IntegerLiteral(..) => {
+                    stack.push(Type::Int);
+                    walked.push(node);
+                }
+                FlatNode::NumberLiteral(..) => {
+                    stack.push(Type::Float);
+                    walked.push(node);
+                }
+                FlatNode::BooleanLiteral(..) => {
+                    stack.push(Type::Bool);
+                    walked.push(node);
+                }
+                FlatNode::StringLiteral(..) => {
+                    stack.push(Type::String);
+                    walked.push(node);
+                }
+                FlatNode::DynamicConstant(ref value, _) => {
+                    stack.push(Type::of_constant(value));
+                    walked.push(node);
+                }
+                FlatNode::Unit(..) => {
+                    stack.push(Type::Unit);
+                    walked.push(node);
+                }
+
+                //the chosen branch runs with the `eval` following the if block
+                FlatNode::IfBlock {
+                    condition,
+                    succeed,
+                    fail,
+                    position,
+                } => {
+                    let condition = self.walk(condition, stack);
+                    let condition_type = stack.pop();
+                    if condition_type.is_known() && condition_type != Type::Bool {
+                        self.error(
+                            format!("expected a bool condition, found {}", condition_type.name()),
+                            position,
+                        );
+                    }
+
+                    let mut fail_stack = stack.clone();
+                    let succeed = self.walk(succeed, stack);
+                    let fail = fail.map(|fail| self.walk(fail, &mut fail_stack));
+                    stack.join(&fail_stack);
+
+                    walked.push(FlatNode::IfBlock {
+                        condition,
+                        succeed,
+                        fail,
+                        position,
+                    });
+
+                    match nodes.peek() {
+                        Some(FlatNode::Op(Op::FnCall(name), _)) if name == "eval" => {
+                            walked.extend(nodes.next())
+                        }
+                        _ => stack.clear(),
+                    }
+                }
+                FlatNode::WhileBlock {
+                    do_while,
+                    condition,
+                    block,
+                    position,
+                } => {
+                    let condition = self.walk(condition, stack);
+                    let block = self.walk(block, stack);
+                    stack.clear();
+
+                    walked.push(FlatNode::WhileBlock {
+                        do_while,
+                        condition,
+                        block,
+                        position,
+                    });
+                }
+                //the body of a user-defined function, stored in the variable that follows it.
+                //it runs with the arguments and then the continuation on the stack
+                FlatNode::Block(body, position) => {
+                    let mut body_stack = match nodes.peek() {
+                        Some(FlatNode::Op(Op::Store(function), _)) => {
+                            match self.arguments.get(function) {
+                                Some(arguments) => Stack {
+                                    values: arguments.clone(),
+                                    below: Type::Unknown,
+                                },
+                                //until a call is seen the arguments are left unassigned,
+                                //so they do not become unknown before their values are known
+                                None if !self.rewrite => Stack {
+                                    values: vec![],
+                                    below: Type::Unassigned,
+                                },
+                                None => Stack::default(),
+                            }
+                        }
+                        _ => Stack::default(),
+                    };
+                    body_stack.push(Type::Unknown);
+
+                    let body = self.walk(body, &mut body_stack);
+                    stack.push(Type::Unknown);
+                    walked.push(FlatNode::Block(body, position));
+                }
+                FlatNode::Property(name, position) => {
+                    match stack.pop() {
+                        Type::Unassigned => stack.push(Type::Unassigned),
+                        Type::Vector => {
+                            stack.push(Type::Float);
+                            if self.rewrite {
+                                walked
+                                    .extend(vector_component(&name, position).unwrap_or_default());
+                                continue;
+                            }
+                        }
+                        Type::Map => stack.push(Type::Unknown),
+                        Type::Unknown => {
+                            stack.push(Type::Unknown);
+                            if self.rewrite {
+                                walked.extend(component_or_property(&name, position));
+                                continue;
+                            }
+                        }
+                        receiver => {
+                            self.error(
+                                format!("`.{name}` cannot be read from {}", receiver.name()),
+                                position,
+                            );
+                            stack.push(Type::Unknown);
+                        }
+                    }
+                    walked.push(FlatNode::Property(name, position));
+                }
+                FlatNode::Method(name, arity, position) => {
+                    let mut args = (0..arity).map(|_| stack.pop()).collect::<Vec<_>>();
+                    let receiver = stack.pop();
+
+                    let string_receiver = match receiver {
+                        Type::Unassigned => None,
+                        Type::String => Some(true),
+                        Type::Array | Type::Map => Some(false),
+                        Type::Unknown => {
+                            self.error(
+                                format!(
+                                    "the receiver of `{name}` could be a string or an array, \
+                                     declare its type with a `//: type` comment"
+                                ),
+                                position,
+                            );
+                            None
+                        }
+                        receiver => {
+                            self.error(
+                                format!("`{name}` cannot be called on {}", receiver.name()),
+                                position,
+                            );
+                            None
+                        }
+                    };
+                    let Some(method) = string_receiver.and_then(|string_receiver| {
+                        method(&name, arity, string_receiver, position)
+                    }) else {
+                        stack.push(match receiver {
+                            Type::Unassigned => Type::Unassigned,
+                            _ => Type::Unknown,
+                        });
+                        walked.push(FlatNode::Method(name, arity, position));
+                        continue;
+                    };
+
+                    stack.push(receiver);
+                    while let Some(arg) = args.pop() {
+                        stack.push(arg);
+                    }
+                    let resolved = self.walk(method.flattened_ast, stack);
+                    if self.rewrite {
+                        walked.extend(resolved);
+                    } else {
+                        walked.push(FlatNode::Method(name, arity, position));
+                    }
+                }
+                FlatNode::Hex(..) => {
+                    stack.clear();
+                    walked.push(node);
+                }
+            }
+        }
+
+        walked
+    }
+
+    fn op(&mut self, op: Op, position: Position, stack: &mut Stack) -> Vec<FlatNode> {
+        match &op {
+            Op::Push(variable) => stack.push(self.variable(variable)),
+            Op::Store(variable) => {
+                let value = stack.pop();
+                self.store(variable, value, position);
+            }
+            Op::Pop => {
+                stack.pop();
+            }
+            Op::FnCall(name) => return self.fn_call(name.clone(), op, position, stack),
+            //only the `[keys, values]` pair of a map is built with the action itself
+            Op::Action(name) if name == "last_n_list" => {
+                stack.clear();
+                stack.push(Type::Map);
+            }
+            Op::Action(name) => action(name, stack),
+            Op::Call(function) => {
+                self.call(function, stack);
+                stack.clear();
+            }
+            Op::Return(_) => stack.clear(),
+        }
+
+        vec![FlatNode::Op(op, position)]
+    }
+
+    fn fn_call(
+        &mut self,
+        name: String,
+        op: Op,
+        position: Position,
+        stack: &mut Stack,
+    ) -> Vec<FlatNode> {
+        let unchanged = vec![FlatNode::Op(op, position)];
+
+        match name.as_str() {
+            "+" | "-" | "*" | "/" | "%" | "**" | "&" | "|" | "^" | "<<" | ">>" | "<" | ">"
+            | "<=" | ">=" => {
+                let (b, a) = (stack.pop(), stack.pop());
+                let Some(result) = binary(&name, a, b) else {
+                    self.error(
+                        format!(
+                            "`{name}` cannot be applied to {} and {}",
+                            a.name(),
+                            b.name()
+                        ),
+                        position,
+                    );
+                    stack.push(Type::Unknown);
+                    return unchanged;
+                };
+                stack.push(result);
+
+                if !self.rewrite {
+                    return unchanged;
+                }
+
+                let action =
+                    |name: &str| vec![FlatNode::Op(Op::Action(name.to_string()), position)];
+                match (name.as_str(), a, b) {
+                    ("&", Type::Bool, Type::Bool) => action("and"),
+                    ("&", Type::Int, Type::Int) => action("and_bit"),
+                    ("|", Type::Bool, Type::Bool) => action("or"),
+                    ("|", Type::Int, Type::Int) => action("or_bit"),
+                    ("+" | "-" | "*" | "/" | "**", Type::Int, Type::Int) => {
+                        let mut rewritten = unchanged;
+                        if name == "/" {
+                            rewritten.extend(truncate(position));
+                        }
+                        rewritten.extend(check_overflow(position));
+                        rewritten
+                    }
+                    _ => unchanged,
+                }
+            }
+            "==" | "!=" | "in" | "contains" => {
+                stack.pop();
+                stack.pop();
+                stack.push(Type::Bool);
+                unchanged
+            }
+            "!" => {
+                let value = stack.pop();
+                if value.is_known() && value != Type::Bool {
+                    self.error(
+                        format!("`!` cannot be applied to {}", value.name()),
+                        position,
+                    );
+                }
+                stack.push(Type::Bool);
+                unchanged
+            }
+            "abs" | "coerce_axial" => {
+                let value = stack.pop();
+                stack.push(value);
+                unchanged
+            }
+            "duplicate" => {
+                let value = stack.pop();
+                stack.push(value);
+                stack.push(value);
+                unchanged
+            }
+            "swap" => {
+                let (b, a) = (stack.pop(), stack.pop());
+                stack.push(b);
+                stack.push(a);
+                unchanged
+            }
+            "over" => {
+                let (b, a) = (stack.pop(), stack.pop());
+                stack.push(a);
+                stack.push(b);
+                stack.push(a);
+                unchanged
+            }
+            "2dup" => {
+                let (b, a) = (stack.pop(), stack.pop());
+                for value in [a, b, a, b] {
+                    stack.push(value);
+                }
+                unchanged
+            }
+            "rotate_reverse" => {
+                let (c, b, a) = (stack.pop(), stack.pop(), stack.pop());
+                for value in [c, a, b] {
+                    stack.push(value);
+                }
+                unchanged
+            }
+            "if" => {
+                let (fail, succeed) = (stack.pop(), stack.pop());
+                stack.pop();
+                stack.push(succeed.join(fail));
+                unchanged
+            }
+            //the number of elements is a literal, which the stack does not keep
+            "last_n_list" => {
+                stack.clear();
+                stack.push(Type::Array);
+                unchanged
+            }
+            "unappend" => {
+                stack.pop();
+                stack.push(Type::Array);
+                stack.push(Type::Unknown);
+                unchanged
+            }
+            _ => {
+                action(&name, stack);
+                unchanged
+            }
+        }
+    }
+}
+
+fn action(name: &str, stack: &mut Stack) {
+    match ACTIONS.iter().find(|(pattern, _, _)| *pattern == name) {
+        Some((_, arity, result)) => {
+            for _ in 0..*arity {
+                stack.pop();
+            }
+            stack.push(*result);
+        }
+        None => stack.clear(),
+    }
+}
+
+//the type of `a op b`, or `None` when rhai has no such operator.
+//vectors take the overloads of hex casting arithmetic
+fn binary(op: &str, a: Type, b: Type) -> Option<Type> {
+    use Type::*;
+
+    let number = |a: Type, b: Type| if a == b { a } else { Float };
+
+    let result = match (op, a, b) {
+        (_, Unassigned, _) | (_, _, Unassigned) => Unassigned,
+        (_, Unknown, _) | (_, _, Unknown) => match op {
+            "<" | ">" | "<=" | ">=" => Bool,
+            _ => Unknown,
+        },
+
+        ("+" | "-" | "*" | "/" | "%" | "**", a, b) if a.is_number() && b.is_number() => {
+            number(a, b)
+        }
+        ("+", String, _) | ("+", _, String) => String,
+        ("+", Array, Array) => Array,
+        ("+", Map, Map) => Map,
+        ("*", Vector, Vector) => Float,
+        ("+" | "-" | "*" | "/" | "**", Vector, Vector) => Vector,
+        ("+" | "-" | "*" | "/" | "**", Vector, b) if b.is_number() => Vector,
+        ("+" | "-" | "*" | "/" | "**", a, Vector) if a.is_number() => Vector,
+
+        ("&" | "|" | "^", Bool, Bool) => Bool,
+        ("&" | "|" | "^" | "<<" | ">>", Int, Int) => Int,
+
+        //hex casting only compares numbers, so strings cannot be ordered
+        ("<" | ">" | "<=" | ">=", a, b) if a.is_number() && b.is_number() => Bool,
+
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+//q -> q rounded towards zero, like rhai's integer division
+fn truncate(position: Position) -> Vec<FlatNode> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+
+    vec![
+        action("duplicate"),
+        action("coerce_axial"),
+        action("swap"),
+        action("abs"),
+        action("floor"),
+        action("*"),
+    ]
+}
+
+//x -> x, dividing by zero when x does not fit in an i32.
+//for integers that is exactly when |x + 0.5| >= 2^31
+fn check_overflow(position: Position) -> Vec<FlatNode> {
+    let action = |name: &str| FlatNode::Op(Op::FnCall(name.to_string()), position);
+    let number = |number: f64| FlatNode::NumberLiteral(number, position);
+
+    vec![
+        action("duplicate"),
+        number(0.5),
+        action("+"),
+        action("abs"),
+        number(2f64.powi(31)),
+        action("<"),
+        number(1.0),
+        number(0.0),
+        action("if"),
+        action("/"),
+    ]
+}
@@ -0,0 +1,21 @@
+mod compiler;
+mod diagnostic;
+mod entity;
+mod flatten_ast;
+mod flatten_chain;
+mod flatten_custom;
+mod flatten_entity;
+mod flatten_methods;
+mod flatten_std;
+mod flatten_switch;
+mod flatten_vector;
+mod hex_module;
+mod infer_types;
+mod translate;
+mod translate_dynamic;
+mod translate_ops;
+mod vector;
+
+pub use compiler::{CompileError, Compiler, Script};
+pub use diagnostic::Diagnostic;
+pub use flatten_ast::{FlatNode, Op};
@@ -1,136 +1,171 @@
-use std::{collections::HashMap, fs};
+use std::{
+    env, fs,
+    io::{self, Read},
+    panic,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
+use cli::{Args, ArgsError, Command, OutputFormat};
 use hexagon::{
-    compiler::{compile_to_iotas, nbt::gen_give_cmd},
+    compiler::nbt::gen_give_cmd,
     interpreter::{error::print_interpreter_error, interpret},
     iota::Iota,
-    parse_config::Config,
+    parse_config::parse_config,
     parser::AstNode,
-    pattern_registry::{PatternRegistry, PatternRegistryExt},
 };
-use rhai::{BinaryExpr, Engine, EvalAltResult, Expr, Expression, FnCallExpr, Ident, Stmt};
-use translate::translate_flattened_ast;
-
-use crate::flatten_ast::flatten_statements;
-
-pub mod flatten_ast;
-pub mod translate;
-pub mod translate_dynamic;
-pub mod translate_ops;
-
-fn main() -> Result<(), Box<EvalAltResult>> {
-    let mut engine = Engine::new();
-
-    let source = fs::read_to_string("./test.rhai").unwrap();
-
-    //will be implemented eventually
-    engine.disable_symbol("&&");
-    engine.disable_symbol("||");
-    engine.disable_symbol("<<");
-    engine.disable_symbol(">>");
-
-    engine.set_strict_variables(true);
-
-
-    let ast = engine.compile(source)?;
-
-    println!("\neval\n");
-    
-    engine.eval_ast(&ast)?;
-
-    println!("Ast: {:#?}", ast.statements());
-
-    println!("Flattened Ast: {:?}", flatten_statements(ast.statements()));
-
-    compile()?;
-
-    run()?;
-
-    Ok(())
-}
-
-fn compile() -> Result<(), Box<EvalAltResult>> {
-    let mut engine = Engine::new();
-
-    let source = fs::read_to_string("./test.rhai").unwrap();
-
-    //will be implemented eventually
-    engine.disable_symbol("&&");
-    engine.disable_symbol("||");
-    engine.disable_symbol("<<");
-    engine.disable_symbol(">>");
+use hexerhai::{CompileError, Compiler, Script};
+use im::Vector;
 
-    let ast = engine.compile(source)?;
+mod cli;
 
-    let translated_ast = translate_flattened_ast(flatten_statements(ast.statements()));
+//the project config that is used when none is given, like hexagon does
+const DEFAULT_CONFIG: &str = "config.toml";
 
-    let config = Config {
-        libraries: HashMap::new(),
-        entities: HashMap::new(),
-        great_spell_sigs: PatternRegistry::gen_default_great_sigs(),
+fn main() -> ExitCode {
+    let args = match cli::parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(ArgsError::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{}", cli::USAGE);
+            return ExitCode::from(2);
+        }
     };
 
-    let pattern_registry = PatternRegistry::construct(&config.great_spell_sigs);
-
-    let compile_result = compile_to_iotas(
-        &hexagon::parser::AstNode::Program(translated_ast),
-        None,
-        &pattern_registry,
-        &HashMap::new(),
-    );
-
-    match compile_result {
-        // Ok(result) => println!("\nresult: {}", Vector::from(result).display()),
-        Ok(result) => println!("\nresult: {}", gen_give_cmd(result)),
-
+    let compiler = match load_compiler(args.config.as_deref()) {
+        Ok(compiler) => compiler,
         Err(err) => {
-            println!("e {:?}", err)
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
         }
     };
 
-    Ok(())
+    let mut output = String::new();
+    let mut failed = false;
+
+    for input in &args.inputs {
+        match process_input(&compiler, &args, input) {
+            Ok(result) => output.push_str(&result),
+            Err(err) => {
+                eprintln!("{err}");
+                failed = true;
+            }
+        }
+    }
+
+    match &args.output {
+        Some(path) => {
+            if let Err(err) = fs::write(path, output) {
+                eprintln!("error: could not write {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{output}"),
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-fn run() -> Result<(), Box<EvalAltResult>> {
-    let mut engine = Engine::new();
+fn load_compiler(config: Option<&Path>) -> Result<Compiler, String> {
+    let path = match config {
+        Some(path) => path.to_path_buf(),
+        None if Path::new(DEFAULT_CONFIG).is_file() => {
+            eprintln!("note: using config {DEFAULT_CONFIG}");
+            PathBuf::from(DEFAULT_CONFIG)
+        }
+        None => return Ok(Compiler::new()),
+    };
 
-    let source = fs::read_to_string("./test.rhai").unwrap();
+    //hexagon reads the config itself and panics if it can't, so check it can be read first
+    fs::read_to_string(&path).map_err(|err| format!("error: config {}: {err}", path.display()))?;
+
+    //and treat a panic while parsing it as an invalid config, without printing the panic message
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let config = panic::catch_unwind(|| parse_config(path.display().to_string()));
+    panic::set_hook(hook);
+
+    let config = config.map_err(|_| {
+        format!(
+            "error: config {} is not a valid project config",
+            path.display()
+        )
+    })?;
+    Ok(Compiler::with_config(config))
+}
 
-    //will be implemented eventually
-    engine.disable_symbol("&&");
-    engine.disable_symbol("||");
-    engine.disable_symbol("<<");
-    engine.disable_symbol(">>");
+fn read_source(input: &Path) -> io::Result<String> {
+    if input == Path::new("-") {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read_to_string(input)
+    }
+}
 
-    
-    let ast = engine.compile(&source)?;
+fn process_input(compiler: &Compiler, args: &Args, input: &Path) -> Result<String, String> {
+    let source_path = input.display().to_string();
+    let source = read_source(input).map_err(|err| format!("error: {source_path}: {err}"))?;
+
+    let script = compiler
+        .parse(&source)
+        .map_err(|err| format!("error: {source_path}: {err}"))?;
+
+    let render = |err: CompileError| err.render(&source, &source_path);
+
+    match args.command {
+        Command::Compile => compile(compiler, &script, args.format)
+            .map(|result| result + "\n")
+            .map_err(render),
+        Command::Check => compile(compiler, &script, args.format)
+            .map(|_| String::new())
+            .map_err(render),
+        Command::Run => run(compiler, &script, &source_path),
+        Command::DumpAst => Ok(format!("{:#?}\n", script.statements())),
+        Command::DumpFlat => compiler
+            .flatten(&script)
+            .map(|flattened_ast| format!("{:#?}\n", flattened_ast))
+            .map_err(render),
+    }
+}
 
-    let translated_ast = translate_flattened_ast(flatten_statements(ast.statements()));
+fn compile(
+    compiler: &Compiler,
+    script: &Script,
+    format: OutputFormat,
+) -> Result<String, CompileError> {
+    let result = compiler.compile_script(script)?;
+
+    Ok(match format {
+        OutputFormat::GiveCmd => gen_give_cmd(result),
+        OutputFormat::Iotas => Vector::from(result).display(),
+    })
+}
 
-    let config = Config {
-        libraries: HashMap::new(),
-        entities: HashMap::new(),
-        great_spell_sigs: PatternRegistry::gen_default_great_sigs(),
-    };
+fn run(compiler: &Compiler, script: &Script, source_path: &str) -> Result<String, String> {
+    let translated_ast = compiler
+        .translate(script)
+        .map_err(|err| err.render(script.source(), source_path))?;
 
-    let interpreter_result = interpret(
+    match interpret(
         AstNode::Program(translated_ast),
-        &config,
-        HashMap::new(),
-        &source,
-        "",
-    );
-
-    match interpreter_result {
-        Ok(result) => println!(
-            "\nresult: {} \n {:?}",
-            result.stack.display(),
-            result.buffer
-        ),
+        compiler.config(),
+        compiler.config().entities.clone(),
+        script.source(),
+        source_path,
+    ) {
+        Ok(result) => Ok(format!("{}\n{:?}\n", result.stack.display(), result.buffer)),
         Err(err) => {
-            print_interpreter_error(err, &source, "test.rhai");
+            print_interpreter_error(err, script.source(), source_path);
+            Err(format!("error: {source_path}: interpreter error"))
         }
-    };
-
-    Ok(())
+    }
 }
@@ -7,23 +7,34 @@ use hexagon::{
 use rhai::Position;
 
 use crate::{
-    flatten_ast::FlatNode, translate_dynamic::translate_dynamic_to_iota,
-    translate_ops::translate_op,
+    diagnostic::Diagnostic, flatten_ast::FlatNode, flatten_chain::map_property,
+    translate_dynamic::translate_dynamic_to_iota, translate_ops::translate_op,
 };
 
-pub fn translate_flattened_ast(ast: Vec<FlatNode>) -> Vec<AstNode> {
+/// Translates the flattened ast into a hexagon program, collecting a diagnostic
+/// for every constant that has no iota.
+pub fn translate_flattened_ast(ast: Vec<FlatNode>) -> Result<Vec<AstNode>, Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    let translated_ast = translate_nodes(ast, &mut diagnostics);
+
+    if diagnostics.is_empty() {
+        Ok(translated_ast)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn translate_nodes(ast: Vec<FlatNode>, diagnostics: &mut Vec<Diagnostic>) -> Vec<AstNode> {
     let mut translated_ast = vec![];
 
     for node in ast {
-        translated_ast.append(&mut translate_node(node));
+        translated_ast.append(&mut translate_node(node, diagnostics));
     }
 
-    println!("translated:\n{:?}", translated_ast);
-
     return translated_ast;
 }
 
-pub fn translate_node(node: FlatNode) -> Vec<AstNode> {
+fn translate_node(node: FlatNode, diagnostics: &mut Vec<Diagnostic>) -> Vec<AstNode> {
     let mut translated = vec![];
 
     match node {
@@ -36,6 +47,11 @@ pub fn translate_node(node: FlatNode) -> Vec<AstNode> {
             name: OpName::IntroEmbed,
             arg: Some(OpValue::Iota(Rc::new(num))),
         }),
+        FlatNode::IntegerLiteral(int, position) => translated.push(AstNode::Op {
+            location: position_to_location(position),
+            name: OpName::IntroEmbed,
+            arg: Some(OpValue::Iota(Rc::new(int as f64))),
+        }),
         FlatNode::BooleanLiteral(bool, position) => translated.push(AstNode::Op {
             location: position_to_location(position),
             name: OpName::IntroEmbed,
@@ -51,23 +67,55 @@ pub fn translate_node(node: FlatNode) -> Vec<AstNode> {
             name: OpName::IntroEmbed,
             arg: Some(OpValue::Iota(Rc::new(NullIota))),
         }),
-        FlatNode::DynamicConstant(val, position) => translated.push(AstNode::Op {
-            location: position_to_location(position),
-            name: OpName::IntroEmbed,
-            arg: Some(OpValue::Iota(translate_dynamic_to_iota(val, position))),
-        }),
+        FlatNode::DynamicConstant(val, position) => {
+            match translate_dynamic_to_iota(val, position) {
+                Ok(iota) => translated.push(AstNode::Op {
+                    location: position_to_location(position),
+                    name: OpName::IntroEmbed,
+                    arg: Some(OpValue::Iota(iota)),
+                }),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
         FlatNode::IfBlock {
             condition,
             succeed,
             fail,
             position,
-        } => translated.push(translate_if(condition, succeed, fail, position)),
+        } => translated.push(translate_if(
+            condition,
+            succeed,
+            fail,
+            position,
+            diagnostics,
+        )),
         FlatNode::WhileBlock {
             do_while,
             condition,
             block,
             position,
-        } => translated.push(translate_while(do_while, condition, block, position)),
+        } => translated.push(translate_while(
+            do_while,
+            condition,
+            block,
+            position,
+            diagnostics,
+        )),
+        FlatNode::Hex(mut nodes, _) => translated.append(&mut nodes),
+        //infer_types only leaves the properties of maps
+        FlatNode::Property(name, position) => translated.append(&mut translate_nodes(
+            map_property(&name, position),
+            diagnostics,
+        )),
+        //infer_types resolves every method it knows the receiver of, and reports the others
+        FlatNode::Method(name, _, position) => diagnostics.push(Diagnostic::new(
+            format!("the receiver of `{name}` has no known type"),
+            position,
+        )),
+        FlatNode::Block(nodes, _) => translated.push(AstNode::Block {
+            external: false,
+            nodes: translate_nodes(nodes, diagnostics),
+        }),
     };
 
     return translated;
@@ -78,41 +126,52 @@ fn translate_if(
     succeed: Vec<FlatNode>,
     fail: Option<Vec<FlatNode>>,
     position: Position,
+    diagnostics: &mut Vec<Diagnostic>,
 ) -> AstNode {
     AstNode::IfBlock {
         condition: Box::new(AstNode::Block {
             external: false,
-            nodes: condition.into_iter().flat_map(translate_node).collect(),
+            nodes: translate_nodes(condition, diagnostics),
         }),
         succeed: Box::new(AstNode::Block {
             external: false,
-            nodes: succeed.into_iter().flat_map(translate_node).collect(),
+            nodes: translate_nodes(succeed, diagnostics),
         }),
         fail: fail.map(|f| {
             Box::new(AstNode::Block {
                 external: false,
-                nodes: f.into_iter().flat_map(translate_node).collect(),
+                nodes: translate_nodes(f, diagnostics),
             })
         }),
         location: position_to_location(position),
     }
 }
 
-fn translate_while(do_while: bool, condition: Vec<FlatNode>, block: Vec<FlatNode>, position: Position) -> AstNode {
+fn translate_while(
+    do_while: bool,
+    condition: Vec<FlatNode>,
+    block: Vec<FlatNode>,
+    position: Position,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> AstNode {
     AstNode::WhileBlock {
         do_while,
         condition: Box::new(AstNode::Block {
             external: false,
-            nodes: condition.into_iter().flat_map(translate_node).collect(),
+            nodes: translate_nodes(condition, diagnostics),
         }),
         block: Box::new(AstNode::Block {
             external: false,
-            nodes: block.into_iter().flat_map(translate_node).collect(),
+            nodes: translate_nodes(block, diagnostics),
         }),
         location: position_to_location(position),
     }
 }
 
+//nodes made up by the compiler can have no position, hexagon's lines start at 1 so 0 marks them
 pub fn position_to_location(position: Position) -> Location {
-    Location::Line(position.line().unwrap(), position.position().unwrap())
+    Location::Line(
+        position.line().unwrap_or_default(),
+        position.position().unwrap_or_default(),
+    )
 }
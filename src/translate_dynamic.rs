@@ -1,40 +1,68 @@
-use hexagon::{
-    iota::{hex_casting::null::NullIota, Iota},
-    parser::{AstNode, OpName},
+use hexagon::iota::{
+    hex_casting::{null::NullIota, vector::VectorIota},
+    Iota,
 };
 use im::vector;
-use rhai::{Dynamic, Position, Shared};
+use rhai::{Dynamic, Map, Position};
 use std::rc::Rc;
 
-pub fn translate_dynamic_to_iota(val: Box<Dynamic>, position: Position) -> Rc<dyn Iota> {
+use crate::{diagnostic::Diagnostic, vector::Vector};
+
+//constants folded by rhai's optimizer, compiled to the iota they are embedded as
+pub fn translate_dynamic_to_iota(
+    val: Box<Dynamic>,
+    position: Position,
+) -> Result<Rc<dyn Iota>, Diagnostic> {
+    let unsupported = |type_name: &str| {
+        Diagnostic::new(
+            format!("constants of type `{type_name}` cannot be compiled to hex casting"),
+            position,
+        )
+    };
+    let type_name = val.type_name();
+
     if val.is_array() {
         let mut translated_array = vector![];
-        let array = val.into_array().unwrap();
+        let array = val.into_array().map_err(unsupported)?;
         for var in array {
-            translated_array.push_back(translate_dynamic_to_iota(Box::new(var), position));
+            translated_array.push_back(translate_dynamic_to_iota(Box::new(var), position)?);
         }
 
-        //AstNode::Op { location: position_to_location(position), name: OpName::Push, arg: Some(hexagon::parser::OpValue::Iota(())) };
+        Ok(Rc::new(translated_array))
+    } else if val.is_map() {
+        //maps are `[keys, values]`, see flatten_chain
+        let mut keys = vector![];
+        let mut values = vector![];
+        let map = val
+            .try_cast::<Map>()
+            .ok_or_else(|| unsupported(type_name))?;
+        for (key, value) in map {
+            keys.push_back(Rc::new(key.to_string()) as Rc<dyn Iota>);
+            values.push_back(translate_dynamic_to_iota(Box::new(value), position)?);
+        }
 
-        Rc::new(translated_array)
+        Ok(Rc::new(vector![
+            Rc::new(keys) as Rc<dyn Iota>,
+            Rc::new(values) as Rc<dyn Iota>
+        ]))
     } else if val.is_bool() {
-        let bool = val.as_bool().unwrap();
-        Rc::new(bool)
+        Ok(Rc::new(val.as_bool().map_err(unsupported)?))
     } else if val.is_char() {
-        let char = val.as_char().unwrap();
-        Rc::new(char.to_string())
+        Ok(Rc::new(val.as_char().map_err(unsupported)?.to_string()))
     } else if val.is_string() {
-        let string = val.into_string().unwrap();
-        Rc::new(string)
+        Ok(Rc::new(val.into_string().map_err(unsupported)?))
     } else if val.is_int() {
-        let int = val.as_int().unwrap();
-        Rc::new(int as f64)
+        Ok(Rc::new(val.as_int().map_err(unsupported)? as f64))
     } else if val.is_float() {
-        let float = val.as_float().unwrap();
-        Rc::new(float)
+        Ok(Rc::new(val.as_float().map_err(unsupported)?))
     } else if val.is_unit() {
-        Rc::new(NullIota)
+        Ok(Rc::new(NullIota))
+    } else if val.is::<Vector>() {
+        let Vector { x, y, z } = val
+            .try_cast::<Vector>()
+            .ok_or_else(|| unsupported(type_name))?;
+        Ok(Rc::new(VectorIota::new(x, y, z)))
     } else {
-        panic!()
+        Err(unsupported(type_name))
     }
 }
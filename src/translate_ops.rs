@@ -4,6 +4,12 @@ use hexagon::parser::{ActionValue, AstNode, Location, OpName};
 
 use crate::flatten_ast::Op;
 
+//rhai operators and functions that translate_fn_call maps to hex casting actions
+pub const BUILTIN_FUNCTIONS: &[&str] = &[
+    "==", "!=", ">", "<", ">=", "<=", "!", "&", "|", "+", "-", "*", "/", "%", "**", "^", "<<", ">>",
+    "..", "..=", "in", "contains", "print",
+];
+
 //takes rhai operators and compiles them to hex casting actions
 pub fn translate_op(op: Op, location: Location) -> Vec<AstNode> {
     match op {
@@ -18,9 +24,42 @@ pub fn translate_op(op: Op, location: Location) -> Vec<AstNode> {
             name: OpName::Push,
             arg: Some(hexagon::parser::OpValue::Var(var)),
         }],
+        Op::Action(name) => vec![AstNode::Action {
+            location,
+            name,
+            value: None,
+        }],
+        Op::Call(var) => translate_op_call(var, location),
+        Op::Return(var) => translate_op_return(var, location),
+        Op::Pop => vec![AstNode::Action {
+            location,
+            name: "mask".to_string(),
+            value: Some(ActionValue::Bookkeeper("v".to_string())),
+        }],
     }
 }
 
+//functions are called with Iris' Gambit so `return` can jump back to the caller
+#[rustfmt::skip]
+fn translate_op_call(var: String, location: Location) -> Vec<AstNode> {
+    let mut actions = vec![];
+
+    actions.push(AstNode::Op { location, name: OpName::Push, arg: Some(hexagon::parser::OpValue::Var(var)) });
+    actions.push(AstNode::Action { location, name: "eval/cc".to_string(), value: None });
+
+    return actions;
+}
+
+#[rustfmt::skip]
+fn translate_op_return(var: String, location: Location) -> Vec<AstNode> {
+    let mut actions = vec![];
+
+    actions.push(AstNode::Op { location, name: OpName::Push, arg: Some(hexagon::parser::OpValue::Var(var)) });
+    actions.push(AstNode::Action { location, name: "eval".to_string(), value: None });
+
+    return actions;
+}
+
 #[rustfmt::skip]
 fn translate_fn_call(fn_name: String, location: Location) -> Vec<AstNode> {
     match fn_name.as_str() {
@@ -36,9 +75,6 @@ fn translate_fn_call(fn_name: String, location: Location) -> Vec<AstNode> {
         "!" => vec![AstNode::Action { location, name: "not".to_string(), value: None }],
         "&" => translate_op_and(location),
         "|" => translate_op_or(location),
-        "||" => todo!(),
-        "&&" => todo!(),
-        "??" => todo!(),
 
         "+" => vec![AstNode::Action { location, name: "add".to_string(), value: None }],
         "-" => vec![AstNode::Action { location, name: "sub".to_string(), value: None }],
@@ -48,18 +84,20 @@ fn translate_fn_call(fn_name: String, location: Location) -> Vec<AstNode> {
         "**" => vec![AstNode::Action { location, name: "pow_proj".to_string(), value: None }],
         "^" => vec![AstNode::Action { location, name: "xor_bit".to_string(), value: None }],
 
-        "<<" => todo!(),
-        ">>" => todo!(),
+        "<<" => translate_op_shift(true, location),
+        ">>" => translate_op_shift(false, location),
 
         ".." => translate_op_range(location),
         "..=" => translate_op_range_inclusive(location),
 
         "in" => translate_op_in(location),
+        //`x in list` is parsed as `contains(list, x)`
+        "contains" => translate_op_in(location),
 
         //builtin functions
         "print" => translate_op_print(location),
 
-        //TODO: handle user-defined functions
+        //hex casting actions, which were checked against the pattern registry while flattening
         _ => vec![AstNode::Action { location, name: fn_name, value: None }],
     }
 
@@ -119,8 +157,8 @@ fn translate_op_or(location: Location) -> Vec<AstNode> {
 
     //if it is, use boolean operator. otherwise, use bitwise operator
     actions.push(AstNode::Action { location, name: "open_paren".to_string(), value:None });
-    actions.push(AstNode::Action { location, name: "and".to_string(), value:None });
-    actions.push(AstNode::Action { location, name: "and_bit".to_string(), value:None });
+    actions.push(AstNode::Action { location, name: "or".to_string(), value:None });
+    actions.push(AstNode::Action { location, name: "or_bit".to_string(), value:None });
     actions.push(AstNode::Action { location, name: "close_paren".to_string(), value:None });
     actions.push(AstNode::Action { location, name: "splat".to_string(), value:None });
     actions.push(AstNode::Action { location, name: "if".to_string(), value:None });
@@ -129,6 +167,92 @@ fn translate_op_or(location: Location) -> Vec<AstNode> {
     return actions;
 }
 
+//rhai's i32 shifts: a negative shift count shifts the other way,
+//and counts of 32 or more shift every bit out
+#[rustfmt::skip]
+fn translate_op_shift(left: bool, location: Location) -> Vec<AstNode> {
+    let mut actions = vec![];
+
+    //replace the shift count with its absolute value, remembering if it was negative
+    actions.push(AstNode::Action { location, name: "duplicate".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(0.0))) });
+    actions.push(AstNode::Action { location, name: "less".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "swap".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "abs".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "swap".to_string(), value: None });
+
+    //a negative count reverses the direction
+    let (negative, positive) = if left {
+        (translate_shift_right(location), translate_shift_left(location))
+    } else {
+        (translate_shift_left(location), translate_shift_right(location))
+    };
+
+    actions.push(AstNode::Action { location, name: "open_paren".to_string(), value: None });
+    actions.extend(negative);
+    actions.push(AstNode::Action { location, name: "close_paren".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "open_paren".to_string(), value: None });
+    actions.extend(positive);
+    actions.push(AstNode::Action { location, name: "close_paren".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "if".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "eval".to_string(), value: None });
+
+    return actions;
+}
+
+//x, n -> x * 2^n, wrapped to an i32
+#[rustfmt::skip]
+fn translate_shift_left(location: Location) -> Vec<AstNode> {
+    let mut actions = translate_clamped_power_of_two(location);
+
+    actions.push(AstNode::Action { location, name: "mul_dot".to_string(), value: None });
+
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2f64.powi(31)))) });
+    actions.push(AstNode::Action { location, name: "add".to_string(), value: None });
+    //modulo keeps the sign of the dividend, so it is applied twice to get a positive remainder
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2f64.powi(32)))) });
+    actions.push(AstNode::Action { location, name: "modulo".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2f64.powi(32)))) });
+    actions.push(AstNode::Action { location, name: "add".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2f64.powi(32)))) });
+    actions.push(AstNode::Action { location, name: "modulo".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2f64.powi(31)))) });
+    actions.push(AstNode::Action { location, name: "sub".to_string(), value: None });
+
+    return actions;
+}
+
+//x, n -> floor(x / 2^n), an arithmetic shift
+#[rustfmt::skip]
+fn translate_shift_right(location: Location) -> Vec<AstNode> {
+    let mut actions = translate_clamped_power_of_two(location);
+
+    actions.push(AstNode::Action { location, name: "div_cross".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "floor".to_string(), value: None });
+
+    return actions;
+}
+
+//n -> 2^min(n, 32), which is enough to shift out every bit of an i32
+#[rustfmt::skip]
+fn translate_clamped_power_of_two(location: Location) -> Vec<AstNode> {
+    let mut actions = vec![];
+
+    actions.push(AstNode::Action { location, name: "duplicate".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(32.0))) });
+    actions.push(AstNode::Action { location, name: "greater".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "swap".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(32.0))) });
+    actions.push(AstNode::Action { location, name: "swap".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "if".to_string(), value: None });
+
+    actions.push(AstNode::Action { location, name: "number".to_string(), value: Some(ActionValue::Iota(Rc::new(2.0))) });
+    actions.push(AstNode::Action { location, name: "swap".to_string(), value: None });
+    actions.push(AstNode::Action { location, name: "pow_proj".to_string(), value: None });
+
+    return actions;
+}
+
 #[rustfmt::skip]
 fn translate_op_range_inclusive(location: Location) -> Vec<AstNode> {
     let mut actions = vec![];
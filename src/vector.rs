@@ -0,0 +1,94 @@
+use std::fmt;
+
+use rhai::{Dynamic, Engine, EvalAltResult, Variant, FLOAT, INT};
+
+/// The rhai side of hex casting vectors, so scripts using them also run in rhai.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector {
+    pub x: FLOAT,
+    pub y: FLOAT,
+    pub z: FLOAT,
+}
+
+impl Vector {
+    pub fn new(x: FLOAT, y: FLOAT, z: FLOAT) -> Self {
+        Vector { x, y, z }
+    }
+
+    fn map(self, f: impl Fn(FLOAT) -> FLOAT) -> Self {
+        Vector::new(f(self.x), f(self.y), f(self.z))
+    }
+
+    fn zip(self, other: Vector, f: impl Fn(FLOAT, FLOAT) -> FLOAT) -> Self {
+        Vector::new(f(self.x, other.x), f(self.y, other.y), f(self.z, other.z))
+    }
+
+    fn dot(self, other: Vector) -> FLOAT {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn cross(self, other: Vector) -> Self {
+        Vector::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+}
+
+//hex casting shows vectors like `(1, 2, 3)`
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+fn number(value: Dynamic) -> Result<FLOAT, Box<EvalAltResult>> {
+    match value.as_int() {
+        Ok(int) => Ok(int as FLOAT),
+        Err(_) => value.as_float().map_err(|type_name| {
+            format!("vector components must be numbers, found {type_name}").into()
+        }),
+    }
+}
+
+/// Registers the `vector` type with `vec(x, y, z)`, the `x`, `y` and `z` properties and
+/// the arithmetic of hex casting: `*` of two vectors is the dot product and `/` the cross product.
+pub fn register_vector(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Vector>("vector")
+        .register_fn(
+            "vec",
+            |x: Dynamic, y: Dynamic, z: Dynamic| -> Result<Vector, Box<EvalAltResult>> {
+                Ok(Vector::new(number(x)?, number(y)?, number(z)?))
+            },
+        )
+        .register_get("x", |vector: &mut Vector| vector.x)
+        .register_get("y", |vector: &mut Vector| vector.y)
+        .register_get("z", |vector: &mut Vector| vector.z)
+        .register_fn("to_string", |vector: &mut Vector| vector.to_string())
+        .register_fn("to_debug", |vector: &mut Vector| vector.to_string())
+        .register_fn("==", |a: Vector, b: Vector| a == b)
+        .register_fn("!=", |a: Vector, b: Vector| a != b)
+        .register_fn("+", |a: Vector, b: Vector| a.zip(b, |a, b| a + b))
+        .register_fn("-", |a: Vector, b: Vector| a.zip(b, |a, b| a - b))
+        .register_fn("*", |a: Vector, b: Vector| a.dot(b))
+        .register_fn("/", |a: Vector, b: Vector| a.cross(b))
+        .register_fn("-", |a: Vector| a.map(|a| -a));
+
+    register_scalar::<INT>(engine);
+    register_scalar::<FLOAT>(engine);
+}
+
+//a number is applied to every component
+fn register_scalar<N: Variant + Clone + Copy + Into<FLOAT>>(engine: &mut Engine) {
+    engine
+        .register_fn("+", |a: Vector, b: N| a.map(|a| a + b.into()))
+        .register_fn("+", |a: N, b: Vector| b.map(|b| a.into() + b))
+        .register_fn("-", |a: Vector, b: N| a.map(|a| a - b.into()))
+        .register_fn("-", |a: N, b: Vector| b.map(|b| a.into() - b))
+        .register_fn("*", |a: Vector, b: N| a.map(|a| a * b.into()))
+        .register_fn("*", |a: N, b: Vector| b.map(|b| a.into() * b))
+        .register_fn("/", |a: Vector, b: N| a.map(|a| a / b.into()))
+        .register_fn("/", |a: N, b: Vector| b.map(|b| a.into() / b));
+}
@@ -0,0 +1,100 @@
+//! How the command line picks up and validates the project config, run against the built
+//! binary from a scratch directory so no `config.toml` is found by accident.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+//a scratch directory holding `script.rhai`, and `config.toml` when a config is given
+fn workspace(name: &str, config: Option<&str>) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("script.rhai"), "let x = 1 + 2;").unwrap();
+    if let Some(config) = config {
+        fs::write(dir.join("config.toml"), config).unwrap();
+    }
+
+    dir
+}
+
+fn check(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hexerhai"))
+        .current_dir(dir)
+        .arg("check")
+        .args(args)
+        .arg("script.rhai")
+        .output()
+        .unwrap()
+}
+
+fn entities_config() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/config/entities.toml");
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn missing_config_is_an_error() {
+    let dir = workspace("missing_config", None);
+
+    let output = check(&dir, &["-c", "missing.toml"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("error: config missing.toml"), "{stderr}");
+}
+
+#[test]
+fn malformed_config_is_an_error() {
+    let dir = workspace("malformed_config", None);
+    fs::write(dir.join("broken.toml"), "[[entities]\nname = ").unwrap();
+
+    let output = check(&dir, &["-c", "broken.toml"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("error: config broken.toml is not a valid project config"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn default_config_is_noted() {
+    let dir = workspace("default_config", Some(&entities_config()));
+
+    let output = check(&dir, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("note: using config config.toml"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn given_config_is_not_noted() {
+    let dir = workspace("given_config", Some(&entities_config()));
+
+    let output = check(&dir, &["-c", "config.toml"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(!stderr.contains("note:"), "{stderr}");
+}
+
+#[test]
+fn no_config_is_not_noted() {
+    let dir = workspace("no_config", None);
+
+    let output = check(&dir, &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.is_empty(), "{stderr}");
+}
@@ -0,0 +1,275 @@
+use hexerhai::{CompileError, Compiler, FlatNode, Op};
+
+fn diagnostics(source: &str) -> Vec<String> {
+    let compiler = Compiler::new();
+    let script = compiler.parse(source).unwrap();
+
+    match compiler.flatten(&script) {
+        Ok(_) => vec![],
+        Err(CompileError::Diagnostics(diagnostics)) => diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect(),
+        Err(err) => panic!("unexpected error: {err}"),
+    }
+}
+
+#[test]
+fn known_functions_resolve() {
+    let source = "fn double(x) { x * 2 } let a = [1, 2]; print(abs(double(-1))); print(2 in a);";
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+}
+
+#[test]
+fn unknown_function_suggests_builtin() {
+    assert_eq!(
+        diagnostics("let x = 1; prnt(x);"),
+        ["unknown function `prnt` with 1 arguments, did you mean `print`?"]
+    );
+}
+
+#[test]
+fn unknown_function_suggests_user_function() {
+    assert_eq!(
+        diagnostics("fn spiral(n) { n } spirl(3);"),
+        ["unknown function `spirl` with 1 arguments, did you mean `spiral`?"]
+    );
+}
+
+#[test]
+fn user_function_with_wrong_arity() {
+    assert_eq!(
+        diagnostics("fn double(x) { x * 2 } double(1, 2);"),
+        ["`double` takes 1 arguments but 2 were given"]
+    );
+    assert_eq!(
+        diagnostics("fn area(w) { w * w } fn area(w, h) { w * h } area();"),
+        ["`area` takes 1 or 2 arguments but 0 were given"]
+    );
+}
+
+#[test]
+fn unknown_function_without_suggestion() {
+    assert_eq!(
+        diagnostics("frobnicate();"),
+        ["unknown function `frobnicate` with 0 arguments"]
+    );
+}
+
+#[test]
+fn unmapped_std_function() {
+    assert_eq!(
+        diagnostics("sinh(1.0);"),
+        ["`sinh` with 1 arguments from rhai's standard library is not supported"]
+    );
+}
+
+#[test]
+fn hex_namespace_resolves() {
+    let source = "let caster = hex::get_caster(); let eye = hex::entity_pos_eye(caster);";
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+}
+
+#[test]
+fn hex_namespace_checks_arity() {
+    assert_eq!(
+        diagnostics("hex::raycast(hex::get_caster());"),
+        ["`hex::raycast` takes 2 arguments but 1 were given"]
+    );
+}
+
+#[test]
+fn hex_namespace_suggests_action() {
+    assert_eq!(
+        diagnostics("hex::get_castr();"),
+        ["unknown hex casting action `hex::get_castr`, did you mean `hex::get_caster`?"]
+    );
+}
+
+#[test]
+fn hex_namespace_only_knows_actions_with_an_arity() {
+    assert_eq!(
+        diagnostics("let list = [1, 2]; hex::splat(list);"),
+        ["unknown hex casting action `hex::splat`"]
+    );
+    assert_eq!(
+        diagnostics("hex::frobnicate(1, 2, 3, 4, 5);"),
+        ["unknown hex casting action `hex::frobnicate`"]
+    );
+}
+
+#[test]
+fn hex_namespace_values_push_one_iota() {
+    assert_eq!(
+        diagnostics("let x = 1; let v = hex::write(x);"),
+        ["`hex::write` pushes 0 iotas, so it can only be called as a statement"]
+    );
+    assert_eq!(
+        diagnostics("let v = vec(1, 2, 3); print(hex::deconstruct_vec(v) + 1);"),
+        ["`hex::deconstruct_vec` pushes 3 iotas, so it can only be called as a statement"]
+    );
+}
+
+#[test]
+fn hex_namespace_statements_pop_results() {
+    let compiler = Compiler::new();
+    let script = compiler
+        .parse("hex::deconstruct_vec(vec(1, 2, 3));")
+        .unwrap();
+    let flattened_ast = compiler.flatten(&script).unwrap();
+
+    let pops = flattened_ast
+        .iter()
+        .rev()
+        .take_while(|node| matches!(node, FlatNode::Op(Op::Pop, _)))
+        .count();
+    assert_eq!(pops, 3);
+}
+
+#[test]
+fn patterns_block_resolves() {
+    let source = "let x = 1; patterns { get_caster; entity_pos/eye } print(x);";
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+}
+
+#[test]
+fn patterns_block_reads_names_as_strings() {
+    let source = r#"patterns { "Mind's Reflection"; "Compass' Purification" }"#;
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+}
+
+#[test]
+fn pattern_signature_resolves() {
+    assert_eq!(
+        diagnostics("print(pattern!(\"qaq\", EAST));"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn pattern_checks_start_direction() {
+    assert_eq!(
+        diagnostics("print(pattern!(\"qaq\", UP));"),
+        ["unknown start direction `UP`, expected one of NORTH_EAST, EAST, SOUTH_EAST, SOUTH_WEST, WEST, NORTH_WEST"]
+    );
+}
+
+#[test]
+fn pattern_checks_signature() {
+    assert_eq!(
+        diagnostics("print(pattern!(\"qqqqqqqqqqqq\", EAST));"),
+        ["no hex casting pattern has the signature \"qqqqqqqqqqqq\""]
+    );
+}
+
+#[test]
+fn operator_type_errors() {
+    assert_eq!(
+        diagnostics("let a = true; let b = a + 1;"),
+        ["`+` cannot be applied to bool and i32"]
+    );
+    assert_eq!(
+        diagnostics("let a = \"text\"; let b = !a;"),
+        ["`!` cannot be applied to string"]
+    );
+    assert_eq!(
+        diagnostics("let a = \"abc\"; let b = \"abd\"; let c = a < b;"),
+        ["`<` cannot be applied to string and string"]
+    );
+    assert_eq!(
+        diagnostics("let x = 1; if x { print(x); }"),
+        ["expected a bool condition, found i32"]
+    );
+}
+
+#[test]
+fn type_hints() {
+    let source = "let target = get_caster(); //: entity\nlet eye = hex::entity_pos_eye(target);";
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+
+    assert_eq!(
+        diagnostics("let target = get_caster(); //: vector"),
+        ["the variable is declared as vector but is assigned entity"]
+    );
+    assert_eq!(
+        diagnostics("let target = get_caster(); //: player"),
+        ["unknown type `player`, expected one of i32, f64, bool, string, array, map, vector, entity, ()"]
+    );
+}
+
+#[test]
+fn entities_come_from_the_config() {
+    assert_eq!(
+        diagnostics("let steve = entity(\"Steve\");"),
+        ["unknown entity `Steve`, entities are declared in the config"]
+    );
+    assert_eq!(
+        diagnostics("let name = \"Steve\"; let steve = entity(name);"),
+        ["`entity` takes the name of an entity from the config as a string literal"]
+    );
+}
+
+#[test]
+fn unsupported_constants() {
+    let compiler = Compiler::new();
+    let script = compiler.parse("let range = 0..3;").unwrap();
+
+    let Err(CompileError::Diagnostics(diagnostics)) = compiler.translate(&script) else {
+        panic!("a constant range has no iota");
+    };
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0]
+        .message
+        .ends_with("cannot be compiled to hex casting"));
+}
+
+#[test]
+fn recursive_functions() {
+    assert_eq!(
+        diagnostics("fn even(n) { n == 0 || odd(n - 1) } fn odd(n) { n != 0 && even(n - 1) }"),
+        [
+            "recursive functions are not supported yet",
+            "recursive functions are not supported yet"
+        ]
+    );
+    assert_eq!(
+        diagnostics("fn first() { second() } fn second() { 1 } first();"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn break_with_value() {
+    assert_eq!(
+        diagnostics("let n = 0; loop { n += 1; if n > 2 { break n; } }"),
+        ["break with a value is not supported"]
+    );
+}
+
+#[test]
+fn methods_follow_the_receiver_type() {
+    let source = r#"let name = "hex"; let items = [1, 2]; print(name.len() + items.len());"#;
+    assert_eq!(diagnostics(source), Vec::<String>::new());
+
+    assert_eq!(
+        diagnostics("fn size(x) { x.len() }"),
+        ["the receiver of `len` could be a string or an array, declare its type with a `//: type` comment"]
+    );
+    assert_eq!(
+        diagnostics("fn size(x) { let list = x; //: array\n list.len() }"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn properties_follow_the_receiver_type() {
+    assert_eq!(diagnostics("fn height(v) { v.y }"), Vec::<String>::new());
+    assert_eq!(
+        diagnostics("fn height(v) { let position = v; //: vector\n position.y }"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        diagnostics("let n = 1; print(n.x);"),
+        ["`.x` cannot be read from i32"]
+    );
+}
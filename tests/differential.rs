@@ -0,0 +1,142 @@
+//! Runs every script in `tests/fixtures` both with rhai and as a compiled hex program,
+//! and checks that both print the same lines and end with the same value.
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    rc::Rc,
+};
+
+use hexagon::{
+    interpreter::interpret,
+    iota::{hex_casting::null::NullIota, Iota},
+    parser::AstNode,
+};
+use hexerhai::Compiler;
+use rhai::Dynamic;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    let mut fixtures = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect::<Vec<_>>();
+    fixtures.sort();
+
+    fixtures
+}
+
+fn eval_rhai(source: &str) -> Result<(Vec<String>, String), String> {
+    let prints = Rc::new(RefCell::new(vec![]));
+
+    //the compiler's engine, so fixtures can use everything the compiler parses
+    let mut compiler = Compiler::new();
+    let captured = prints.clone();
+    compiler
+        .engine_mut()
+        .on_print(move |line| captured.borrow_mut().push(line.to_string()));
+
+    let value = compiler
+        .engine()
+        .eval::<Dynamic>(source)
+        .map_err(|err| format!("rhai error: {err}"))?;
+    let value = compiler
+        .value_to_iota(value)
+        .map_err(|err| format!("rhai result: {err}"))?
+        .display();
+
+    Ok((prints.take(), value))
+}
+
+fn eval_hex(source: &str) -> Result<String, String> {
+    let compiler = Compiler::new();
+    let script = compiler.parse(source).map_err(|err| err.to_string())?;
+    let program = compiler.translate(&script).map_err(|err| err.to_string())?;
+
+    let result = interpret(
+        AstNode::Program(program),
+        compiler.config(),
+        compiler.config().entities.clone(),
+        source,
+        "",
+    )
+    .map_err(|_| "interpreter error".to_string())?;
+
+    Ok(match result.stack.last() {
+        Some(iota) => iota.display(),
+        None => NullIota.display(),
+    })
+}
+
+//hexagon prints straight to stdout, so the prints are taken from the driver,
+//which ends its output with the stack and the buffer
+fn hex_prints(path: &Path) -> Result<Vec<String>, String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_hexerhai"))
+        .arg("run")
+        .arg(path)
+        .output()
+        .map_err(|err| err.to_string())?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::to_string).collect::<Vec<_>>();
+    lines.truncate(lines.len().saturating_sub(2));
+
+    Ok(lines)
+}
+
+//rhai prints `3` and `hex` where hex casting shows `3.00` and `"hex"`
+fn normalize(line: &str) -> String {
+    let line = line.trim();
+    match line.parse::<f64>() {
+        Ok(number) => number.to_string(),
+        Err(_) => line.trim_matches('"').to_string(),
+    }
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    let (rhai_prints, rhai_value) = eval_rhai(&source)?;
+    let hex_value = eval_hex(&source)?;
+    let hex_prints = hex_prints(path)?;
+
+    let rhai_prints = rhai_prints.iter().map(|line| normalize(line));
+    let hex_prints = hex_prints.iter().map(|line| normalize(line));
+    if !rhai_prints.clone().eq(hex_prints.clone()) {
+        return Err(format!(
+            "printed output differs\n  rhai: {:?}\n  hex:  {:?}",
+            rhai_prints.collect::<Vec<_>>(),
+            hex_prints.collect::<Vec<_>>()
+        ));
+    }
+
+    if rhai_value != hex_value {
+        return Err(format!(
+            "final value differs\n  rhai: {rhai_value}\n  hex:  {hex_value}"
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn fixtures_match_rhai() {
+    let failures = fixtures()
+        .iter()
+        .filter_map(|path| {
+            check(path)
+                .err()
+                .map(|err| format!("{}: {err}", path.display()))
+        })
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
@@ -0,0 +1,64 @@
+//! Scripts referencing entities declared in a project config, compiled and run against
+//! the mocked world hexagon builds from the config.
+
+use std::path::Path;
+
+use hexagon::{interpreter::interpret, parse_config::parse_config, parser::AstNode};
+use hexerhai::Compiler;
+
+fn compiler() -> Compiler {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/config/entities.toml");
+    Compiler::with_config(parse_config(path.display().to_string()))
+}
+
+#[test]
+fn entity_compiles() {
+    let compiler = compiler();
+
+    let iotas = compiler.compile(r#"let steve = entity("Steve");"#).unwrap();
+    assert!(!iotas.is_empty());
+}
+
+#[test]
+fn entity_runs_against_the_config() {
+    let compiler = compiler();
+    let source = r#"let steve = entity("Steve"); steve == entity("Steve")"#;
+
+    let rhai_result = compiler.engine().eval::<bool>(source).unwrap();
+    assert!(rhai_result);
+
+    let script = compiler.parse(source).unwrap();
+    let program = compiler.translate(&script).unwrap();
+    let result = interpret(
+        AstNode::Program(program),
+        compiler.config(),
+        compiler.config().entities.clone(),
+        source,
+        "",
+    )
+    .unwrap_or_else(|_| panic!("interpreter error in `{source}`"));
+
+    assert_eq!(
+        result.stack.last().unwrap().display(),
+        compiler.value_to_iota(true.into()).unwrap().display()
+    );
+}
+
+#[test]
+fn entity_is_pushed_by_name() {
+    let compiler = compiler();
+    let source = r#"entity("Steve")"#;
+
+    let script = compiler.parse(source).unwrap();
+    let program = compiler.translate(&script).unwrap();
+    let result = interpret(
+        AstNode::Program(program),
+        compiler.config(),
+        compiler.config().entities.clone(),
+        source,
+        "",
+    )
+    .unwrap_or_else(|_| panic!("interpreter error in `{source}`"));
+
+    assert!(result.stack.last().unwrap().display().contains("Steve"));
+}
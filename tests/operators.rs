@@ -0,0 +1,182 @@
+use hexagon::{
+    interpreter::interpret,
+    parser::{AstNode, Location, OpName, OpValue},
+};
+use hexerhai::Compiler;
+use rhai::Dynamic;
+
+//operands are bound to variables so rhai's optimizer cannot fold the operator away
+fn source(lhs: &str, op: &str, rhs: &str) -> String {
+    format!("let a = {lhs}; let b = {rhs}; let result = a {op} b;")
+}
+
+fn eval_rhai(compiler: &Compiler, source: &str) -> String {
+    let result = compiler
+        .engine()
+        .eval::<Dynamic>(&format!("{source} result"))
+        .unwrap();
+
+    compiler.value_to_iota(result).unwrap().display()
+}
+
+fn eval_hex(compiler: &Compiler, source: &str) -> String {
+    let script = compiler.parse(source).unwrap();
+    let mut program = compiler.translate(&script).unwrap();
+    program.push(AstNode::Op {
+        location: Location::Line(1, 1),
+        name: OpName::Push,
+        arg: Some(OpValue::Var("result".to_string())),
+    });
+
+    let result = interpret(
+        AstNode::Program(program),
+        compiler.config(),
+        compiler.config().entities.clone(),
+        source,
+        "",
+    )
+    .unwrap_or_else(|_| panic!("interpreter error in `{source}`"));
+
+    result.stack.last().unwrap().display()
+}
+
+fn assert_op(lhs: &str, op: &str, rhs: &str) {
+    let compiler = Compiler::new();
+    let source = source(lhs, op, rhs);
+
+    assert_eq!(
+        eval_rhai(&compiler, &source),
+        eval_hex(&compiler, &source),
+        "`{lhs} {op} {rhs}`"
+    );
+}
+
+#[test]
+fn comparison() {
+    for op in ["==", "!=", ">", "<", ">=", "<="] {
+        assert_op("1", op, "2");
+        assert_op("2", op, "2");
+        assert_op("-3", op, "2");
+    }
+}
+
+#[test]
+fn arithmetic() {
+    for op in ["+", "-", "*"] {
+        assert_op("7", op, "2");
+        assert_op("-7", op, "3");
+    }
+    assert_op("6", "/", "3");
+    assert_op("7", "%", "3");
+    assert_op("2", "**", "10");
+    assert_op("1.5", "+", "2.25");
+}
+
+#[test]
+fn integer_division() {
+    assert_op("7", "/", "2");
+    assert_op("-7", "/", "2");
+    assert_op("7", "/", "-2");
+    assert_op("7.0", "/", "2.0");
+    assert_op("-7", "%", "3");
+    assert_op("7", "%", "-3");
+}
+
+#[test]
+fn negation() {
+    let compiler = Compiler::new();
+
+    for value in ["5", "-3", "2.5", "0"] {
+        let source = format!("let a = {value}; let result = -a + +a * 2;");
+        assert_eq!(
+            eval_rhai(&compiler, &source),
+            eval_hex(&compiler, &source),
+            "`-{value}`"
+        );
+    }
+}
+
+#[test]
+fn integer_overflow() {
+    let compiler = Compiler::new();
+
+    for (lhs, op, rhs) in [
+        ("2147483647", "+", "1"),
+        ("-2147483647 - 1", "/", "-1"),
+        ("2", "**", "31"),
+    ] {
+        let source = source(lhs, op, rhs);
+        assert!(
+            compiler.engine().run(&source).is_err(),
+            "`{lhs} {op} {rhs}`"
+        );
+
+        let script = compiler.parse(&source).unwrap();
+        let program = compiler.translate(&script).unwrap();
+        let result = interpret(
+            AstNode::Program(program),
+            compiler.config(),
+            compiler.config().entities.clone(),
+            &source,
+            "",
+        );
+        assert!(result.is_err(), "`{lhs} {op} {rhs}` did not mishap");
+    }
+}
+
+#[test]
+fn bitwise_and() {
+    assert_op("6", "&", "3");
+    assert_op("-1", "&", "12");
+    assert_op("true", "&", "false");
+    assert_op("true", "&", "true");
+}
+
+#[test]
+fn bitwise_or() {
+    assert_op("6", "|", "3");
+    assert_op("-8", "|", "3");
+    assert_op("true", "|", "false");
+    assert_op("false", "|", "false");
+}
+
+#[test]
+fn bitwise_xor() {
+    assert_op("6", "^", "3");
+    assert_op("-8", "^", "3");
+}
+
+#[test]
+fn shifts() {
+    for op in ["<<", ">>"] {
+        assert_op("5", op, "2");
+        assert_op("-5", op, "2");
+        assert_op("5", op, "-2");
+        assert_op("1", op, "31");
+        assert_op("-1", op, "32");
+        assert_op("123", op, "100");
+    }
+}
+
+//`x in list` is parsed as `contains(list, x)`
+#[test]
+fn contains() {
+    assert_op("2", "in", "[1, 2, 3]");
+    assert_op("5", "in", "[1, 2, 3]");
+    assert_op("true", "in", "[]");
+}
+
+#[test]
+fn short_circuit() {
+    for op in ["&&", "||"] {
+        assert_op("true", op, "false");
+        assert_op("false", op, "true");
+        assert_op("true", op, "true");
+    }
+}
+
+#[test]
+fn coalesce() {
+    assert_op("()", "??", "2");
+    assert_op("1", "??", "2");
+}
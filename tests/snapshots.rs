@@ -0,0 +1,94 @@
+//! Golden-file tests for every script in `tests/fixtures`: the flattened ast, the translated
+//! hexagon ast and the give command are compared against `tests/snapshots`.
+//!
+//! Missing snapshots are created from the current output and should be committed.
+//! Run with `UPDATE_SNAPSHOTS=1` to re-bless the snapshots after an intended change.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use hexagon::compiler::nbt::gen_give_cmd;
+use hexerhai::Compiler;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    let mut fixtures = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect::<Vec<_>>();
+    fixtures.sort();
+
+    fixtures
+}
+
+fn stages(source: &str) -> Result<[(&'static str, String); 3], String> {
+    let compiler = Compiler::new();
+    let script = compiler.parse(source).map_err(|err| err.to_string())?;
+
+    let flattened_ast = compiler.flatten(&script).map_err(|err| err.to_string())?;
+    let translated_ast = compiler.translate(&script).map_err(|err| err.to_string())?;
+    let iotas = compiler
+        .compile_script(&script)
+        .map_err(|err| err.to_string())?;
+
+    Ok([
+        ("flat", format!("{:#?}\n", flattened_ast)),
+        ("hex", format!("{:#?}\n", translated_ast)),
+        ("give", format!("{}\n", gen_give_cmd(iotas))),
+    ])
+}
+
+fn check(path: &Path, update: bool) -> Vec<String> {
+    let name = path.file_stem().unwrap().to_string_lossy();
+    let snapshots = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+
+    let source = fs::read_to_string(path).unwrap();
+    let stages = match stages(&source) {
+        Ok(stages) => stages,
+        Err(err) => return vec![format!("{name}: {err}")],
+    };
+
+    let mut failures = vec![];
+
+    for (stage, actual) in stages {
+        let snapshot = snapshots.join(format!("{name}.{stage}.snap"));
+
+        if update {
+            fs::create_dir_all(&snapshots).unwrap();
+            fs::write(&snapshot, actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{} does not match\n--- expected\n{expected}\n+++ actual\n{actual}",
+                snapshot.display()
+            )),
+            //a new fixture or stage starts out with the output of its first run
+            Err(_) => {
+                fs::create_dir_all(&snapshots).unwrap();
+                fs::write(&snapshot, actual).unwrap();
+                eprintln!("created {}", snapshot.display());
+            }
+        }
+    }
+
+    failures
+}
+
+#[test]
+fn snapshots_match() {
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let failures = fixtures()
+        .iter()
+        .flat_map(|path| check(path, update))
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}